@@ -1,6 +1,6 @@
 use cgmath::Vector3;
-use forte_cubes::{terrain::{chunk::Chunk, blocks::*}, define_blocks_materials};
-use forte_engine::{component_app::EngineComponent, inputs::winit_input::EngineInput, lights::{lights::LightUniform, LightEngine}, primitives::{cameras::Camera, textures::Texture, transforms::TransformRaw, vertices::Vertex}, render::{pipelines::Pipeline, render_engine::RenderEngine, render_utils}, run_app, utils::{camera_controller::CameraController, resources::Handle}, EngineApp};
+use forte_cubes::{culling::Frustum, terrain::{animations::TileAnimations, atlas::{BlockAtlas, Tile}, block_models::BlockModels, chunk::Chunk, blocks::*, vertices::ChunkVertex}, define_blocks_materials};
+use forte_engine::{component_app::EngineComponent, inputs::winit_input::EngineInput, lights::{lights::LightUniform, LightEngine}, primitives::{cameras::Camera, transforms::TransformRaw}, render::{pipelines::Pipeline, render_engine::RenderEngine, render_utils}, run_app, utils::camera_controller::CameraController, EngineApp};
 use winit::event::ElementState;
 
 define_blocks_materials!(
@@ -14,7 +14,7 @@ define_blocks_materials!(
         },
         GRASS => {
             transparent: false,
-            renderer: BlockRenderer::Standard(0, 1, 0, 0, 0, 0)
+            renderer: BlockRenderer::Standard(Tile::Index(0), Tile::Index(1), Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0))
         }
     ]
 );
@@ -28,7 +28,7 @@ pub struct MainApp {
     camera: Camera, 
     controller: CameraController,
     chunk: Chunk<Blocks, Material>,
-    chunk_atlas: Handle<Texture>
+    chunk_atlas: BlockAtlas
 }
 
 #[include_wgsl_oil::include_wgsl_oil("../shaders/terrain.wgsl")]
@@ -48,7 +48,8 @@ impl EngineApp for MainApp {
 
         // create chunk
         let mut chunk = Chunk::empty(0);
-        let chunk_atlas = engine.load_texture(Blocks::ATLAS);
+        let mut chunk_atlas = BlockAtlas::load(&engine, Blocks::ATLAS, 16);
+        chunk_atlas.set_animations(&engine, TileAnimations::from_definitions::<Blocks, Material>());
 
         for x in 0 .. 15 {
             for z in 0 .. 15 {
@@ -65,7 +66,7 @@ impl EngineApp for MainApp {
             }
         }
 
-        chunk.gen_mesh(&mut engine, &chunk_atlas);
+        chunk.gen_mesh(&engine, RenderResources::new(&chunk_atlas, &BlockModels::new()));
 
         // setup light engine
         let mut light_engine = LightEngine::create(&mut engine);
@@ -85,10 +86,10 @@ impl EngineApp for MainApp {
         Self {
             pipeline: Pipeline::new(
                 "chunk", &engine, terrain_shader::SOURCE,
-                &[Vertex::desc(), TransformRaw::desc()],
+                &[ChunkVertex::desc(), TransformRaw::desc()],
                 &[
                     &engine.device.create_bind_group_layout(&Camera::BIND_LAYOUT),
-                    &engine.device.create_bind_group_layout(&Texture::BIND_LAYOUT),
+                    &engine.device.create_bind_group_layout(&BlockAtlas::BIND_LAYOUT),
                     &engine.device.create_bind_group_layout(&LightUniform::BIND_LAYOUT)
                 ],
                 true
@@ -116,6 +117,7 @@ impl EngineApp for MainApp {
         self.controller.update_camera(&mut self.camera);
         self.camera.update(&mut self.render_engine);
         self.light_engine.update(&mut self.render_engine);
+        self.chunk_atlas.update(&self.render_engine);
 
         // start render
         let resources = render_utils::prepare_render(&self.render_engine);
@@ -154,7 +156,9 @@ impl EngineApp for MainApp {
             self.pipeline.bind(&mut pass);
             self.camera.bind(&mut pass, 0);
             self.light_engine.render(&self.render_engine, &mut pass);
-            self.chunk.draw(&self.render_engine, &mut pass, &self.chunk_atlas);
+            if self.chunk.is_visible(&Frustum::from_camera(&self.camera)) {
+                self.chunk.draw(&self.render_engine, &mut pass, &self.chunk_atlas);
+            }
         }
 
         // end render
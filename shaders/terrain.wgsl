@@ -10,7 +10,11 @@ var<uniform> camera: CameraUniform;
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
-    @location(2) normal: vec3<f32>
+    @location(2) normal: vec3<f32>,
+    @location(3) layer: u32,
+    @location(4) animation: u32,
+    @location(12) color: vec3<f32>,
+    @location(13) triplanar: u32
 }
 
 struct InstanceInput {
@@ -28,8 +32,32 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) world_normal: vec3<f32>,
     @location(2) world_position: vec3<f32>,
+    @location(3) @interpolate(flat) layer: u32,
+    @location(4) @interpolate(flat) next_layer: u32,
+    @location(5) @interpolate(flat) blend: f32,
+    @location(6) color: vec3<f32>,
+    @location(7) @interpolate(flat) triplanar: u32,
 }
 
+struct TileAnimation {
+    start: u32,
+    count: u32,
+    length: f32,
+    interpolate: u32
+}
+
+struct AnimationFrame {
+    layer: u32,
+    duration: f32
+}
+
+@group(1) @binding(2)
+var<storage, read> animations: array<TileAnimation>;
+@group(1) @binding(3)
+var<storage, read> frames: array<AnimationFrame>;
+@group(1) @binding(4)
+var<uniform> time: vec4<f32>;
+
 @vertex
 fn vs_main(
     model: VertexInput,
@@ -52,6 +80,27 @@ fn vs_main(
 
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
+    out.color = model.color;
+    out.triplanar = model.triplanar;
+    out.layer = model.layer;
+    out.next_layer = model.layer;
+    out.blend = 0.0;
+
+    // find the current frame of animated tiles
+    if (model.animation > 0u) {
+        let animation = animations[model.animation - 1u];
+        var remaining = time.x % max(animation.length, 0.0001);
+        for (var i = 0u; i < animation.count; i += 1u) {
+            let frame = frames[animation.start + i];
+            if (remaining < frame.duration || i == animation.count - 1u) {
+                out.layer = frame.layer;
+                out.next_layer = frames[animation.start + (i + 1u) % animation.count].layer;
+                if (animation.interpolate != 0u) { out.blend = clamp(remaining / max(frame.duration, 0.0001), 0.0, 1.0); }
+                break;
+            }
+            remaining -= frame.duration;
+        }
+    }
     out.world_normal = normal_matrix * model.normal;
     var world_position = model_matrix * vec4<f32>(model.position, 1.0);
     out.world_position = world_position.xyz;
@@ -62,13 +111,37 @@ fn vs_main(
 // Fragment shader
 
 @group(1) @binding(0)
-var t_diffuse: texture_2d<f32>;
+var t_diffuse: texture_2d_array<f32>;
 @group(1) @binding(1)
 var s_diffuse: sampler;
 
+// samples the current and next frame of a tile with the given derivatives, which lets it be called outside of uniform control flow
+fn sample_tile(layer: u32, next_layer: u32, blend: f32, tex_coords: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {
+    let current = textureSampleGrad(t_diffuse, s_diffuse, tex_coords, layer, ddx, ddy);
+    let next = textureSampleGrad(t_diffuse, s_diffuse, tex_coords, next_layer, ddx, ddy);
+    return mix(current, next, blend);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let diffuse = textureSample(t_diffuse, s_diffuse, in.tex_coords);
-    let color = diffuse.xyz * Lights::calculate_lights(camera.view_pos.xyz, in.world_position, in.world_normal);
+    // derivatives must be taken before branching
+    let tex_ddx = dpdx(in.tex_coords);
+    let tex_ddy = dpdy(in.tex_coords);
+    let world_ddx = dpdx(in.world_position);
+    let world_ddy = dpdy(in.world_position);
+
+    var diffuse: vec4<f32>;
+    if (in.triplanar == 0u) {
+        diffuse = sample_tile(in.layer, in.next_layer, in.blend, in.tex_coords, tex_ddx, tex_ddy);
+    } else {
+        // project the tile along each axis, blending by how much the normal points along that axis
+        var weights = pow(abs(normalize(in.world_normal)), vec3<f32>(4.0));
+        weights = weights / (weights.x + weights.y + weights.z);
+        let p = in.world_position;
+        diffuse = sample_tile(in.layer, in.next_layer, in.blend, fract(vec2<f32>(p.z, -p.y)), vec2<f32>(world_ddx.z, -world_ddx.y), vec2<f32>(world_ddy.z, -world_ddy.y)) * weights.x
+            + sample_tile(in.layer, in.next_layer, in.blend, fract(p.xz), world_ddx.xz, world_ddy.xz) * weights.y
+            + sample_tile(in.layer, in.next_layer, in.blend, fract(vec2<f32>(p.x, -p.y)), vec2<f32>(world_ddx.x, -world_ddx.y), vec2<f32>(world_ddy.x, -world_ddy.y)) * weights.z;
+    }
+    let color = diffuse.xyz * in.color * Lights::calculate_lights(camera.view_pos.xyz, in.world_position, in.world_normal);
     return vec4<f32>(color, diffuse.a);
 }
@@ -0,0 +1,141 @@
+use cgmath::*;
+use forte_engine::primitives::cameras::Camera;
+
+/// An axis aligned bounding box in world space, used to skip drawing objects outside of the cameras view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>
+}
+
+impl Aabb {
+    /// Creates a bounding box from its minimum and maximum corners.
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self { Self { min, max } }
+
+    /// Creates the smallest bounding box that contains all of the given points, or `None` if there are no points.
+    pub fn from_points(points: impl IntoIterator<Item = Vector3<f32>>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self::new(first, first), |aabb, point| aabb.expand(point)))
+    }
+
+    /// Returns this bounding box grown to contain the given point.
+    pub fn expand(&self, point: Vector3<f32>) -> Self {
+        Self {
+            min: Vector3 { x: self.min.x.min(point.x), y: self.min.y.min(point.y), z: self.min.z.min(point.z) },
+            max: Vector3 { x: self.max.x.max(point.x), y: self.max.y.max(point.y), z: self.max.z.max(point.z) }
+        }
+    }
+
+    /// Returns the smallest bounding box that contains both this and the given bounding box.
+    pub fn union(&self, other: &Aabb) -> Self { self.expand(other.min).expand(other.max) }
+
+    /// The center of this bounding box.
+    pub fn center(&self) -> Vector3<f32> { (self.min + self.max) * 0.5 }
+
+    /// The 8 corners of this bounding box.
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vector3 { x: min.x, y: min.y, z: min.z },
+            Vector3 { x: max.x, y: min.y, z: min.z },
+            Vector3 { x: min.x, y: max.y, z: min.z },
+            Vector3 { x: max.x, y: max.y, z: min.z },
+            Vector3 { x: min.x, y: min.y, z: max.z },
+            Vector3 { x: max.x, y: min.y, z: max.z },
+            Vector3 { x: min.x, y: max.y, z: max.z },
+            Vector3 { x: max.x, y: max.y, z: max.z }
+        ]
+    }
+
+    /// Returns the bounding box that contains this bounding box after it is transformed by the given matrix.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        Self::from_points(self.corners().map(|corner| (matrix * corner.extend(1.0)).truncate())).unwrap()
+    }
+}
+
+/// The 6 planes of a cameras view, used to test if bounding volumes can be seen.
+///
+/// Each plane is stored as `(normal, distance)` packed into a `Vector4` with the normal facing into the view, so a point is inside a plane when `dot(normal, point) + distance >= 0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// The planes in the order left, right, bottom, top, near, far.
+    pub planes: [Vector4<f32>; 6]
+}
+
+impl Frustum {
+    /// Extracts a frustum from a view projection matrix whose clip space depth goes from 0 to 1, like the matrices made by `Camera`.
+    pub fn from_matrix(matrix: Matrix4<f32>) -> Self {
+        let (r0, r1, r2, r3) = (matrix.row(0), matrix.row(1), matrix.row(2), matrix.row(3));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2].map(|plane| {
+            let length = plane.truncate().magnitude();
+            if length > 0.0 { plane / length } else { plane }
+        });
+        Self { planes }
+    }
+
+    /// Extracts the frustum of the given camera from its view projection matrix.
+    pub fn from_camera(camera: &Camera) -> Self { Self::from_matrix(camera.build_view_projection_matrix()) }
+
+    /// Returns true if the given point is inside this frustum.
+    pub fn contains_point(&self, point: Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(point) + plane.w >= 0.0)
+    }
+
+    /// Returns true if any part of the given sphere is inside this frustum.
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+
+    /// Returns true if the given bounding box may be inside this frustum.  Boxes near the corners of the frustum can pass even when they are not visible, but visible boxes never fail.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // test the corner furthest along the planes normal
+            let corner = Vector3 {
+                x: if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                y: if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                z: if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z }
+            };
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an orthographic view of the box from -10 to 10 on x and y and 0 to 100 on z
+    fn frustum() -> Frustum { Frustum::from_matrix(Matrix4::from_nonuniform_scale(0.1, 0.1, 0.01)) }
+
+    #[test]
+    fn planes_are_normalized_and_face_inwards() {
+        let frustum = frustum();
+        for plane in frustum.planes { assert!((plane.truncate().magnitude() - 1.0).abs() < 1e-6); }
+        assert!(frustum.contains_point(Vector3::new(0.0, 0.0, 50.0)));
+        assert!(!frustum.contains_point(Vector3::new(0.0, 0.0, -1.0)));
+        assert!(!frustum.contains_point(Vector3::new(11.0, 0.0, 50.0)));
+    }
+
+    #[test]
+    fn boxes_inside_the_frustum_intersect() {
+        assert!(frustum().intersects_aabb(&Aabb::new(Vector3::new(-1.0, -1.0, 10.0), Vector3::new(1.0, 1.0, 20.0))));
+    }
+
+    #[test]
+    fn boxes_outside_a_plane_do_not_intersect() {
+        let frustum = frustum();
+        assert!(!frustum.intersects_aabb(&Aabb::new(Vector3::new(11.0, -1.0, 10.0), Vector3::new(12.0, 1.0, 20.0))));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Vector3::new(-1.0, -12.0, 10.0), Vector3::new(1.0, -11.0, 20.0))));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Vector3::new(-1.0, -1.0, -5.0), Vector3::new(1.0, 1.0, -1.0))));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Vector3::new(-1.0, -1.0, 101.0), Vector3::new(1.0, 1.0, 110.0))));
+    }
+
+    #[test]
+    fn boxes_straddling_a_plane_intersect() {
+        let frustum = frustum();
+        assert!(frustum.intersects_aabb(&Aabb::new(Vector3::new(9.0, -1.0, 10.0), Vector3::new(12.0, 1.0, 20.0))));
+        assert!(frustum.intersects_aabb(&Aabb::new(Vector3::new(-1.0, -1.0, -5.0), Vector3::new(1.0, 1.0, 5.0))));
+        assert!(frustum.intersects_aabb(&Aabb::new(Vector3::new(-20.0, -20.0, -20.0), Vector3::new(20.0, 20.0, 200.0))));
+    }
+}
@@ -1,2 +1,4 @@
+pub mod culling;
 pub mod models;
-pub mod terrain;
\ No newline at end of file
+pub mod terrain;
+pub mod vox;
\ No newline at end of file
@@ -3,14 +3,15 @@ use std::marker::PhantomData;
 use cgmath::*;
 use forte_engine::{math::transforms::Transform, primitives::textures::Texture, render::render_engine::RenderEngine, utils::resources::Handle};
 
-use crate::models::data::*;
+use crate::{culling::{Aabb, Frustum}, models::data::*};
 
 #[derive(Debug)]
 pub struct CubeModel {
     pub transform: Transform,
     pub texture: Handle<Texture>,
     pub bone: CubeModelBone,
-    pub(crate) data: CubeModelData
+    pub(crate) data: CubeModelData,
+    bounds: Option<Aabb>
 }
 
 impl CubeModel {
@@ -34,9 +35,16 @@ impl CubeModel {
         let instances = CubeModel::render_bones(&transform, &bone, texture_size, 16.0, possible_size);
         
         // return new instance of self
-        return Self { transform, texture, bone, data: CubeModelData::new(engine, instances) }
+        let bounds = CubeModel::instance_bounds(&instances);
+        return Self { transform, texture, bone, data: CubeModelData::new(engine, instances), bounds }
     }
 
+    /// The bounding box of all cubes in this model as of the last update, or `None` if the model has no cubes.
+    pub fn bounds(&self) -> Option<Aabb> { self.bounds }
+
+    /// Returns true if any cube of this model may be seen inside the given frustum, so it should be drawn.
+    pub fn is_visible(&self, frustum: &Frustum) -> bool { self.bounds.is_some_and(|bounds| frustum.intersects_aabb(&bounds)) }
+
     /// Updates this cube model so that any changes to this models transform or any of its parts transforms is reflected by the model.
     /// 
     /// # Arguments
@@ -51,6 +59,7 @@ impl CubeModel {
 
         // render cube instances and update data buffer
         let instances = CubeModel::render_bones(&self.transform, &self.bone, texture_size, 16.0, self.data.size as usize);
+        self.bounds = CubeModel::instance_bounds(&instances);
         self.data.update(engine, instances);
     }
 
@@ -71,6 +80,16 @@ impl CubeModel {
         return result;
     }
 
+    /// Gets the bounding box that contains all of the given cube instances, or `None` if there are none.
+    pub(crate) fn instance_bounds(instances: &[CubeInstance]) -> Option<Aabb> {
+        instances.iter().map(|instance| instance.bounds()).reduce(|a, b| a.union(&b))
+    }
+
+    /// Renders a bone into cube instances with the given offset matrix and rotation, like `render_bones` but for offsets that are not a single `Transform`.
+    pub(crate) fn render_bones_matrix(result: &mut Vec<CubeInstance>, matrix: &Matrix4<f32>, rotation: Quaternion<f32>, bone: &CubeModelBone, texture_size: Vector2<f32>, px_per_unit: f32) {
+        CubeModel::recr_render_bone(result, matrix, rotation, bone, texture_size, px_per_unit);
+    }
+
     fn recr_render_bone(result: &mut Vec<CubeInstance>, previous: &Matrix4<f32>, rotation: Quaternion<f32>, bone: &CubeModelBone, texture_size: Vector2<f32>, px_per_unit: f32) {
         let bone_matrix = previous * bone.transform.to_mat();
         let rotation = bone.transform.rotation * rotation;
@@ -115,6 +134,7 @@ impl CubeModel {
             texture: Handle::<Texture> { hash: self.texture.hash, data: PhantomData::default() },
             bone: self.bone.clone(),
             data: CubeModelData::new(engine, CubeModel::render_bones(&self.transform, &self.bone, texture_size, 16.0, self.data.size as usize)),
+            bounds: self.bounds
         }
     }
 }
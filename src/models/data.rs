@@ -1,7 +1,9 @@
-use cgmath::{Vector2, Vector3, Quaternion, Matrix3};
+use cgmath::{Vector2, Vector3, Quaternion, Matrix3, Matrix4};
 use forte_engine::{render::render_engine::RenderEngine, math::transforms::Transform};
 use wgpu::util::DeviceExt;
 
+use crate::culling::Aabb;
+
 #[derive(Debug, Clone)]
 pub struct CubeModelBone {
     pub label: Option<String>,
@@ -88,6 +90,11 @@ impl CubeInstance {
         }
     }
 
+    /// The bounding box of this instances cube in the world.  Cubes are drawn from a unit cube centered on the origin that is transformed by `model`.
+    pub fn bounds(&self) -> Aabb {
+        Aabb::new(Vector3 { x: -0.5, y: -0.5, z: -0.5 }, Vector3 { x: 0.5, y: 0.5, z: 0.5 }).transform(&Matrix4::from(self.model))
+    }
+
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
         wgpu::VertexBufferLayout {
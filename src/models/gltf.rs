@@ -0,0 +1,325 @@
+use std::{fmt::Display, fs, io::Cursor};
+
+use cgmath::*;
+use forte_engine::{math::transforms::Transform, render::render_engine::RenderEngine};
+use image::RgbaImage;
+use serde_json::{json, Value};
+
+use crate::models::{animations::AnimController, cubes::CubeModel, data::{CubeInstance, CubeModelBone}, INDICES, VERTICES};
+
+/// The errors that can occur while exporting a model as glTF.
+#[derive(Debug)]
+pub enum GltfError {
+    /// A glb file could not be written.
+    Io(String, std::io::Error),
+    /// The texture of the model could not be encoded as a PNG.
+    Image(image::ImageError),
+    /// The glTF json could not be written.
+    Json(serde_json::Error),
+    /// A sample of the named `BakedAnimation` does not have a transform for every bone of the model.
+    SamplesMismatch { animation: String, expected: usize, found: usize }
+}
+
+impl Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "Could not write glb file {}: {}", path, err),
+            Self::Image(err) => write!(f, "Could not encode model texture: {}", err),
+            Self::Json(err) => write!(f, "Could not write glTF json: {}", err),
+            Self::SamplesMismatch { animation, expected, found } => write!(f, "Baked animation {} has samples of {} bones but the model has {} bones", animation, found, expected)
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+/// An animation of a bone hierarchy sampled at points in time, so it can be exported with `export_glb`.
+///
+/// Samples store the transform of every bone in the order the bones are found depth first, so every sample should be taken from the same bone hierarchy.
+///
+/// Example
+/// ```rust,ignore
+/// let mut walk = BakedAnimation::new("walk");
+/// // each frame while the animation plays
+/// walk.sample_controller(&engine, &mut controller, &mut model.bone, time);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BakedAnimation {
+    /// The name of the animation in the exported file.
+    pub name: String,
+    times: Vec<f32>,
+    samples: Vec<Vec<Transform>>
+}
+
+impl BakedAnimation {
+    /// Creates an animation with no samples.
+    pub fn new(name: impl Into<String>) -> Self { Self { name: name.into(), times: Vec::new(), samples: Vec::new() } }
+
+    /// The number of samples taken.
+    pub fn len(&self) -> usize { self.times.len() }
+
+    /// Returns true if no samples have been taken.  Empty animations are left out of exported files.
+    pub fn is_empty(&self) -> bool { self.times.is_empty() }
+
+    /// Records the transforms of the given bone and its children at the given time in seconds.  Samples must be taken in order of time.
+    pub fn sample(&mut self, time: f32, root: &CubeModelBone) {
+        let mut transforms = Vec::new();
+        collect_transforms(root, &mut transforms);
+        self.times.push(time);
+        self.samples.push(transforms);
+    }
+
+    /// Updates the given controller on the given bone, then records the bone like `sample`.
+    pub fn sample_controller<T: AnimController>(&mut self, engine: &RenderEngine, controller: &mut T, root: &mut CubeModelBone, time: f32) {
+        controller.update(engine, root);
+        self.sample(time, root);
+    }
+}
+
+fn collect_transforms(bone: &CubeModelBone, result: &mut Vec<Transform>) {
+    result.push(bone.transform);
+    bone.children.iter().for_each(|child| collect_transforms(child, result));
+}
+
+impl CubeModel {
+    /// Exports this model as a binary glTF 2.0 file, see `export_glb`.
+    pub fn to_glb(&self, texture: &RgbaImage, animations: &[BakedAnimation]) -> Result<Vec<u8>, GltfError> { export_glb(&self.transform, &self.bone, texture, animations) }
+
+    /// Saves this model as a binary glTF 2.0 file at the given path, see `export_glb`.
+    pub fn save_glb(&self, path: &str, texture: &RgbaImage, animations: &[BakedAnimation]) -> Result<(), GltfError> {
+        fs::write(path, self.to_glb(texture, animations)?).map_err(|err| GltfError::Io(path.to_string(), err))
+    }
+}
+
+/// Exports a cube model as a binary glTF 2.0 (.glb) file, so it can be rendered in other tools.
+///
+/// The model transform is the root node, each bone is a node under its parent and each part is a node under its bone with a box mesh.  Box texture coordinates are taken from the same texture layout as `CubeInstance`, and the texture is embedded as a PNG.
+///
+/// Arguments:
+/// * transform: &Transform - The transform of the model.
+/// * bone: &CubeModelBone - The root bone of the model.
+/// * texture: &RgbaImage - The texture of the model, usually loaded from the same image as the models texture.
+/// * animations: &[BakedAnimation] - Animations sampled from the bone, each of which becomes a glTF animation.
+///
+/// Returns the bytes of the glb file, or an error if an animation was sampled from a different bone hierarchy or the texture could not be encoded.
+pub fn export_glb(transform: &Transform, bone: &CubeModelBone, texture: &RgbaImage, animations: &[BakedAnimation]) -> Result<Vec<u8>, GltfError> {
+    let mut builder = GlbBuilder { bin: Vec::new(), buffer_views: Vec::new(), accessors: Vec::new() };
+    let texture_size = Vector2 { x: texture.width() as f32, y: texture.height() as f32 };
+
+    // every box shares its positions, normals and indices
+    let positions: Vec<f32> = VERTICES.iter().flat_map(|vertex| vertex.position).collect();
+    let normals: Vec<f32> = VERTICES.iter().flat_map(|vertex| vertex.normal).collect();
+    let positions = builder.accessor(bytemuck::cast_slice(&positions), 5126, VERTICES.len(), "VEC3", Some(34962), Some((vec![-0.5; 3], vec![0.5; 3])));
+    let normals = builder.accessor(bytemuck::cast_slice(&normals), 5126, VERTICES.len(), "VEC3", Some(34962), None);
+    let indices = builder.accessor(bytemuck::cast_slice(INDICES), 5123, INDICES.len(), "SCALAR", Some(34963), None);
+
+    // create a node for the model, then every bone and part below it
+    let mut nodes = vec![json!({ "name": "model", "children": [1] })];
+    transform_json(&mut nodes[0], transform);
+    let mut meshes = Vec::new();
+    let mut bone_nodes = Vec::new();
+    add_bone(&mut builder, &mut nodes, &mut meshes, &mut bone_nodes, bone, texture_size, (positions, normals, indices));
+
+    // every sample needs a transform for every bone
+    for animation in animations.iter() {
+        if let Some(sample) = animation.samples.iter().find(|sample| sample.len() != bone_nodes.len()) {
+            return Err(GltfError::SamplesMismatch { animation: animation.name.clone(), expected: bone_nodes.len(), found: sample.len() })
+        }
+    }
+
+    // embed the texture
+    let mut png = Vec::new();
+    texture.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png).map_err(GltfError::Image)?;
+    let image_view = builder.view(&png, None);
+
+    // add a channel for each part of each bone transform, sampled linearly between samples
+    let animations: Vec<Value> = animations.iter().filter(|animation| !animation.is_empty()).map(|animation| {
+        let start = animation.times[0];
+        let end = animation.times[animation.times.len() - 1];
+        let input = builder.accessor(bytemuck::cast_slice(&animation.times), 5126, animation.len(), "SCALAR", None, Some((vec![start], vec![end])));
+
+        let mut samplers = Vec::new();
+        let mut channels = Vec::new();
+        for (index, node) in bone_nodes.iter().enumerate() {
+            let transforms: Vec<&Transform> = animation.samples.iter().map(|sample| &sample[index]).collect();
+            let translations: Vec<f32> = transforms.iter().flat_map(|transform| vector_array(transform.position)).collect();
+            let rotations: Vec<f32> = transforms.iter().flat_map(|transform| quaternion_array(transform.rotation)).collect();
+            let scales: Vec<f32> = transforms.iter().flat_map(|transform| vector_array(transform.scale)).collect();
+
+            for (path, values, kind) in [("translation", translations, "VEC3"), ("rotation", rotations, "VEC4"), ("scale", scales, "VEC3")] {
+                let output = builder.accessor(bytemuck::cast_slice(&values), 5126, animation.len(), kind, None, None);
+                channels.push(json!({ "sampler": samplers.len(), "target": { "node": node, "path": path } }));
+                samplers.push(json!({ "input": input, "output": output, "interpolation": "LINEAR" }));
+            }
+        }
+        json!({ "name": animation.name, "samplers": samplers, "channels": channels })
+    }).collect();
+
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "forte_cubes" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": [{
+            "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 }, "metallicFactor": 0.0, "roughnessFactor": 1.0 },
+            "alphaMode": "MASK"
+        }],
+        "textures": [{ "source": 0, "sampler": 0 }],
+        "samplers": [{ "magFilter": 9728, "minFilter": 9728 }],
+        "images": [{ "bufferView": image_view, "mimeType": "image/png" }],
+        "buffers": [{ "byteLength": builder.bin.len() }],
+        "bufferViews": builder.buffer_views,
+        "accessors": builder.accessors
+    });
+    if !animations.is_empty() { document["animations"] = Value::Array(animations); }
+
+    // write the header, then the json and binary chunks, each padded to 4 bytes
+    let mut json = serde_json::to_vec(&document).map_err(GltfError::Json)?;
+    while !json.len().is_multiple_of(4) { json.push(b' '); }
+    while !builder.bin.len().is_multiple_of(4) { builder.bin.push(0); }
+    let length = 12 + 8 + json.len() + 8 + builder.bin.len();
+    let mut result = Vec::with_capacity(length);
+    result.extend(b"glTF");
+    result.extend(2u32.to_le_bytes());
+    result.extend((length as u32).to_le_bytes());
+    result.extend((json.len() as u32).to_le_bytes());
+    result.extend(b"JSON");
+    result.extend(json);
+    result.extend((builder.bin.len() as u32).to_le_bytes());
+    result.extend(b"BIN\0");
+    result.extend(builder.bin);
+    Ok(result)
+}
+
+// Adds a node for the given bone with a node for each of its parts, then adds its children below it.
+fn add_bone(builder: &mut GlbBuilder, nodes: &mut Vec<Value>, meshes: &mut Vec<Value>, bone_nodes: &mut Vec<usize>, bone: &CubeModelBone, texture_size: Vector2<f32>, shared: (usize, usize, usize)) -> usize {
+    let index = nodes.len();
+    let mut node = json!({});
+    if let Some(label) = bone.label.as_ref() { node["name"] = json!(label); }
+    transform_json(&mut node, &bone.transform);
+    nodes.push(node);
+    bone_nodes.push(index);
+
+    let mut children = Vec::new();
+    for part in bone.parts.iter() {
+        // map the texture coordinates of the box to the texture splits of the part, like the cube shader
+        let splits = CubeInstance::generate(&Matrix4::identity(), &Quaternion::one(), &part.transform.scale, &part.tex_offset, &texture_size, 16.0).tex_splits;
+        let tex_coords: Vec<f32> = VERTICES.iter().flat_map(|vertex| {
+            let u = match vertex.tex_coords[0] { 0.0 => splits[0][0], 0.25 => splits[0][1], 0.5 => splits[0][2], 0.75 => splits[0][3], _ => splits[1][0] };
+            let v = match vertex.tex_coords[1] { 0.0 => splits[1][1], 0.5 => splits[1][2], _ => splits[1][3] };
+            [u, v]
+        }).collect();
+        let tex_coords = builder.accessor(bytemuck::cast_slice(&tex_coords), 5126, VERTICES.len(), "VEC2", Some(34962), None);
+
+        let (positions, normals, indices) = shared;
+        meshes.push(json!({ "primitives": [{ "attributes": { "POSITION": positions, "NORMAL": normals, "TEXCOORD_0": tex_coords }, "indices": indices, "material": 0 }] }));
+        let mut node = json!({ "mesh": meshes.len() - 1 });
+        transform_json(&mut node, &part.transform);
+        children.push(nodes.len());
+        nodes.push(node);
+    }
+
+    for child in bone.children.iter() { children.push(add_bone(builder, nodes, meshes, bone_nodes, child, texture_size, shared)); }
+    if !children.is_empty() { nodes[index]["children"] = json!(children); }
+    index
+}
+
+fn transform_json(node: &mut Value, transform: &Transform) {
+    node["translation"] = json!(vector_array(transform.position));
+    node["rotation"] = json!(quaternion_array(transform.rotation));
+    node["scale"] = json!(vector_array(transform.scale));
+}
+
+fn vector_array(vector: Vector3<f32>) -> [f32; 3] { vector.into() }
+
+// glTF stores quaternions as x, y, z, w
+fn quaternion_array(rotation: Quaternion<f32>) -> [f32; 4] { [rotation.v.x, rotation.v.y, rotation.v.z, rotation.s] }
+
+// Collects the binary buffer of a glb file with its buffer views and accessors.
+struct GlbBuilder {
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>
+}
+
+impl GlbBuilder {
+    // Adds the given bytes as a buffer view aligned to 4 bytes, returning its index.
+    fn view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        while !self.bin.len().is_multiple_of(4) { self.bin.push(0); }
+        let mut view = json!({ "buffer": 0, "byteOffset": self.bin.len(), "byteLength": bytes.len() });
+        if let Some(target) = target { view["target"] = json!(target); }
+        self.bin.extend_from_slice(bytes);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    // Adds the given bytes as a buffer view with an accessor, returning the index of the accessor.
+    fn accessor(&mut self, bytes: &[u8], component_type: u32, count: usize, kind: &str, target: Option<u32>, bounds: Option<(Vec<f32>, Vec<f32>)>) -> usize {
+        let view = self.view(bytes, target);
+        let mut accessor = json!({ "bufferView": view, "componentType": component_type, "count": count, "type": kind });
+        if let Some((min, max)) = bounds {
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::data::CubeModelPart;
+
+    // a root bone with one part and a labelled child bone with two parts
+    fn bone() -> CubeModelBone {
+        let part = CubeModelPart { transform: Transform::default(), tex_offset: Vector2::zero() };
+        let child = CubeModelBone { label: Some("arm".to_string()), transform: Transform::default(), children: Vec::new(), parts: vec![part, part] };
+        CubeModelBone { label: None, transform: Transform::default(), children: vec![child], parts: vec![part] }
+    }
+
+    // reads the little endian u32 at the given offset
+    fn read_u32(bytes: &[u8], offset: usize) -> usize { u32::from_le_bytes(bytes[offset .. offset + 4].try_into().unwrap()) as usize }
+
+    #[test]
+    fn glb_chunks_are_padded_and_fill_the_file() {
+        let mut walk = BakedAnimation::new("walk");
+        walk.sample(0.0, &bone());
+        walk.sample(1.0, &bone());
+        let glb = export_glb(&Transform::default(), &bone(), &RgbaImage::new(16, 16), &[walk]).unwrap();
+
+        // the header
+        assert_eq!(&glb[0 .. 4], b"glTF");
+        assert_eq!(read_u32(&glb, 4), 2);
+        assert_eq!(read_u32(&glb, 8), glb.len());
+
+        // the json chunk, then the binary chunk up to the end of the file
+        let json_length = read_u32(&glb, 12);
+        assert_eq!(&glb[16 .. 20], b"JSON");
+        let bin_start = 20 + json_length;
+        let bin_length = read_u32(&glb, bin_start);
+        assert_eq!(&glb[bin_start + 4 .. bin_start + 8], b"BIN\0");
+        assert_eq!(bin_start + 8 + bin_length, glb.len());
+        assert!(json_length.is_multiple_of(4) && bin_length.is_multiple_of(4));
+
+        // the model node, a node for each bone and a node with a mesh for each part
+        let document: Value = serde_json::from_slice(&glb[20 .. bin_start]).unwrap();
+        let nodes = document["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 1 + 2 + 3);
+        assert_eq!(nodes[1 ..].iter().filter(|node| node.get("mesh").is_none()).count(), 2);
+        assert_eq!(nodes.iter().filter(|node| node["name"] == "arm").count(), 1);
+        assert_eq!(document["meshes"].as_array().unwrap().len(), 3);
+        assert_eq!(document["animations"][0]["channels"].as_array().unwrap().len(), 2 * 3);
+    }
+
+    #[test]
+    fn samples_must_have_every_bone() {
+        let mut walk = BakedAnimation::new("walk");
+        walk.sample(0.0, &bone().children[0]);
+        match export_glb(&Transform::default(), &bone(), &RgbaImage::new(16, 16), &[walk]) {
+            Err(GltfError::SamplesMismatch { animation, expected, found }) => assert_eq!((animation.as_str(), expected, found), ("walk", 2, 1)),
+            other => panic!("Expected a samples mismatch, got {:?}", other.map(|glb| glb.len()))
+        }
+    }
+}
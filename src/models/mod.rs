@@ -7,6 +7,8 @@ pub mod animations;
 pub mod cubes;
 pub mod data;
 pub mod file;
+pub mod gltf;
+pub mod vox;
 
 const VERTICES: &[Vertex] = &[
     // south Z-
@@ -0,0 +1,178 @@
+use std::io::Cursor;
+
+use cgmath::*;
+use forte_engine::{math::transforms::Transform, render::render_engine::RenderEngine};
+use image::{Rgba, RgbaImage};
+
+use crate::{models::{cubes::CubeModel, data::{CubeModelBone, CubeModelPart}}, vox::{VoxError, VoxFile, VoxModel}};
+
+/// A box of voxels of the same colour, made by merging neighbouring voxels with `VoxModel::cuboids`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoxCuboid {
+    /// The location of the minimum corner of the box in the model.
+    pub min: Vector3<usize>,
+    /// The size of the box in voxels.
+    pub size: Vector3<usize>,
+    /// The colour index of every voxel in the box.
+    pub colour: u8
+}
+
+impl VoxModel {
+    /// Merges the voxels of this model into boxes of the same colour, growing each box along X, then Z, then Y as far as it can.  Every voxel is in exactly one box.
+    pub fn cuboids(&self) -> Vec<VoxCuboid> {
+        let size = self.size;
+        let index = |location: Vector3<usize>| (location.x * size.y + location.y) * size.z + location.z;
+        let mut colours = vec![0u8; size.x * size.y * size.z];
+        self.voxels.iter().for_each(|(location, colour)| colours[index(*location)] = *colour);
+
+        let mut result = Vec::new();
+        for y in 0 .. size.y {
+            for z in 0 .. size.z {
+                for x in 0 .. size.x {
+                    let min = Vector3 { x, y, z };
+                    let colour = colours[index(min)];
+                    if colour == 0 { continue }
+
+                    // a box can grow if every voxel of the new slice has its colour and is not in another box yet
+                    let fits = |colours: &[u8], from: Vector3<usize>, to: Vector3<usize>| {
+                        (from.x .. to.x).all(|x| (from.y .. to.y).all(|y| (from.z .. to.z).all(|z| colours[index(Vector3 { x, y, z })] == colour)))
+                    };
+                    let mut max = min + Vector3::from_value(1);
+                    while max.x < size.x && fits(&colours, Vector3 { x: max.x, ..min }, Vector3 { x: max.x + 1, ..max }) { max.x += 1; }
+                    while max.z < size.z && fits(&colours, Vector3 { z: max.z, ..min }, Vector3 { z: max.z + 1, ..max }) { max.z += 1; }
+                    while max.y < size.y && fits(&colours, Vector3 { y: max.y, ..min }, Vector3 { y: max.y + 1, ..max }) { max.y += 1; }
+
+                    // clear the voxels of the box so they are not used again
+                    for x in min.x .. max.x { for y in min.y .. max.y { for z in min.z .. max.z { colours[index(Vector3 { x, y, z })] = 0; } } }
+                    result.push(VoxCuboid { min, size: max - min, colour });
+                }
+            }
+        }
+        result
+    }
+}
+
+impl VoxFile {
+    /// Converts the model at the given index into a bone with one part per merged box of voxels, and a palette texture with a block of colour for each colour used.
+    ///
+    /// Each voxel is one pixel of its part, so the model is 1/16th of a unit per voxel like other cube models.  The model is centered on the X and Z axes with its bottom at 0.
+    ///
+    /// Returns the bone and the RGBA8 palette texture its parts sample their colours from, or `VoxError::MissingModel` if the model is not in this file.
+    pub fn as_cube_bone(&self, model: usize) -> Result<(CubeModelBone, RgbaImage), VoxError> {
+        let model = self.models.get(model).ok_or(VoxError::MissingModel(model))?;
+        let cuboids = model.cuboids();
+
+        // each colour gets a block large enough for the texture layout of its largest box, with a 1 pixel border so colours do not bleed into each other
+        let mut blocks: Vec<(u8, Vector2<u32>)> = Vec::new();
+        for cuboid in cuboids.iter() {
+            let layout = Vector2 { x: 2 * (cuboid.size.x + cuboid.size.z) as u32 + 2, y: (cuboid.size.y + cuboid.size.z) as u32 + 2 };
+            match blocks.iter_mut().find(|(colour, _)| *colour == cuboid.colour) {
+                Some((_, size)) => { size.x = size.x.max(layout.x); size.y = size.y.max(layout.y); },
+                None => blocks.push((cuboid.colour, layout))
+            }
+        }
+
+        // pack the blocks into rows
+        let width = blocks.iter().map(|(_, size)| size.x).max().unwrap_or(1).max(256);
+        let mut offsets: Vec<(u8, Vector2<u32>)> = Vec::with_capacity(blocks.len());
+        let mut cursor = Vector2 { x: 0, y: 0 };
+        let mut row_height = 0;
+        for (colour, size) in blocks.iter() {
+            if cursor.x + size.x > width { cursor = Vector2 { x: 0, y: cursor.y + row_height }; row_height = 0; }
+            offsets.push((*colour, cursor));
+            cursor.x += size.x;
+            row_height = row_height.max(size.y);
+        }
+        let height = (cursor.y + row_height).max(1);
+
+        let mut texture = RgbaImage::new(width, height);
+        for ((colour, offset), (_, size)) in offsets.iter().zip(blocks.iter()) {
+            for x in 0 .. size.x { for y in 0 .. size.y { texture.put_pixel(offset.x + x, offset.y + y, Rgba(self.colour(*colour))); } }
+        }
+
+        // create a part for each box, centered on its box and offset into its colour block past the border
+        let center = Vector3 { x: model.size.x as f32 / 2.0, y: 0.0, z: model.size.z as f32 / 2.0 };
+        let parts = cuboids.iter().map(|cuboid| {
+            let offset = offsets.iter().find(|(colour, _)| *colour == cuboid.colour).unwrap().1 + Vector2::from_value(1);
+            let size = cuboid.size.cast::<f32>().unwrap();
+            CubeModelPart {
+                transform: Transform {
+                    position: (cuboid.min.cast::<f32>().unwrap() + size / 2.0 - center) * 0.0625,
+                    rotation: Quaternion::one(),
+                    scale: size * 0.0625
+                },
+                tex_offset: Vector2 { x: offset.x as f32 / width as f32, y: offset.y as f32 / height as f32 }
+            }
+        }).collect();
+
+        Ok((CubeModelBone { label: None, transform: Transform::default(), children: Vec::new(), parts }, texture))
+    }
+
+    /// Converts the model at the given index into a `CubeModel`, see `as_cube_bone`.
+    ///
+    /// Arguments:
+    /// * engine: &mut RenderEngine - The engine to create the palette texture and model buffers with.
+    /// * name: &str - The name to give the palette texture, which should be unique for each model.
+    /// * model: usize - The index of the model in this file.
+    ///
+    /// Returns the model, or an error if the model is not in this file or its palette texture could not be encoded.
+    pub fn as_model(&self, engine: &mut RenderEngine, name: &str, model: usize) -> Result<CubeModel, VoxError> {
+        let (bone, texture) = self.as_cube_bone(model)?;
+        let mut png = Vec::new();
+        texture.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png).map_err(VoxError::Image)?;
+        let texture = engine.create_texture(name, &png);
+        Ok(CubeModel::new(engine, Transform::default(), texture, bone, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // makes a file with a single model of the given size and voxels, where colour 1 is red and colour 2 is blue
+    fn file(size: Vector3<usize>, voxels: Vec<(Vector3<usize>, u8)>) -> VoxFile {
+        let mut palette = [[0, 0, 0, 255]; 256];
+        palette[1] = [255, 0, 0, 255];
+        palette[2] = [0, 0, 255, 255];
+        VoxFile { models: vec![VoxModel { size, voxels }], palette }
+    }
+
+    #[test]
+    fn voxels_of_the_same_colour_merge() {
+        // a 2x2 red square next to a blue column
+        let voxels = vec![
+            (Vector3::new(0, 0, 0), 1), (Vector3::new(1, 0, 0), 1), (Vector3::new(2, 0, 0), 2),
+            (Vector3::new(0, 1, 0), 1), (Vector3::new(1, 1, 0), 1), (Vector3::new(2, 1, 0), 2)
+        ];
+        let cuboids = file(Vector3::new(3, 2, 1), voxels).models[0].cuboids();
+        assert_eq!(cuboids, vec![
+            VoxCuboid { min: Vector3::new(0, 0, 0), size: Vector3::new(2, 2, 1), colour: 1 },
+            VoxCuboid { min: Vector3::new(2, 0, 0), size: Vector3::new(1, 2, 1), colour: 2 }
+        ]);
+    }
+
+    #[test]
+    fn different_colours_never_merge() {
+        let voxels = (0 .. 4).map(|x| (Vector3::new(x, 0, 0), 1 + (x % 2) as u8)).collect();
+        let cuboids = file(Vector3::new(4, 1, 1), voxels).models[0].cuboids();
+        assert_eq!(cuboids.len(), 4);
+        assert!(cuboids.iter().all(|cuboid| cuboid.size == Vector3::new(1, 1, 1)));
+        assert_eq!(cuboids.iter().map(|cuboid| cuboid.colour).collect::<Vec<_>>(), vec![1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn parts_sample_their_palette_colour() {
+        let voxels = vec![(Vector3::new(0, 0, 0), 1), (Vector3::new(1, 0, 0), 1), (Vector3::new(2, 0, 0), 2), (Vector3::new(0, 0, 1), 2)];
+        let file = file(Vector3::new(3, 1, 2), voxels);
+        let (bone, texture) = file.as_cube_bone(0).unwrap();
+        let cuboids = file.models[0].cuboids();
+        assert_eq!(bone.parts.len(), cuboids.len());
+
+        for (part, cuboid) in bone.parts.iter().zip(cuboids.iter()) {
+            let pixel = Vector2 { x: (part.tex_offset.x * texture.width() as f32).round() as u32, y: (part.tex_offset.y * texture.height() as f32).round() as u32 };
+            assert_eq!(texture.get_pixel(pixel.x, pixel.y).0, file.colour(cuboid.colour));
+            assert_eq!(part.transform.scale, cuboid.size.cast::<f32>().unwrap() * 0.0625);
+        }
+        assert!(matches!(file.as_cube_bone(1), Err(VoxError::MissingModel(1))));
+    }
+}
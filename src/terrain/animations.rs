@@ -0,0 +1,144 @@
+use crate::terrain::{atlas::{Tile, TileNames}, blocks::*};
+
+/// A single frame of a `TileAnimation`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationFrame {
+    /// The tile shown during this frame.  This cannot be another animated tile.
+    pub tile: Tile,
+    /// How long this frame is shown for in seconds.
+    pub duration: f32
+}
+
+/// An animated atlas tile that cycles through a list of frames.  Use it on a block face with `Tile::Animated`.
+///
+/// The current frame is picked in the terrain shader from the time given to `BlockAtlas::update`, so chunk meshes do not need to be rebuilt as it plays.
+///
+/// Example
+/// ```rust,ignore
+/// const WATER: TileAnimation = TileAnimation {
+///     frames: &[
+///         AnimationFrame { tile: Tile::Named("water_0"), duration: 0.25 },
+///         AnimationFrame { tile: Tile::Named("water_1"), duration: 0.25 }
+///     ],
+///     interpolate: true
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileAnimation {
+    /// The frames of this animation in order.
+    pub frames: &'static [AnimationFrame],
+    /// If true, each frame is blended into the next over its duration instead of switching instantly.
+    pub interpolate: bool
+}
+
+impl TileAnimation {
+    /// The total length of this animation in seconds.
+    pub fn length(&self) -> f32 { self.frames.iter().map(|frame| frame.duration).sum() }
+}
+
+/// A list of the `TileAnimation`s used by a set of blocks.  Each animation is referenced in chunk meshes by its index in this list plus 1, where 0 means not animated.
+#[derive(Debug, Clone, Default)]
+pub struct TileAnimations {
+    animations: Vec<TileAnimation>
+}
+
+impl TileAnimations {
+    /// Creates an empty list of animations.
+    pub fn new() -> Self { Self::default() }
+
+    /// Collects every `TileAnimation` used by the given block definitions.
+    pub fn from_definitions<T: BlockDefinitions<M>, M: MaterialDef + 'static>() -> Self {
+        let mut animations = Self::new();
+        T::DEFINITIONS.iter()
+            .flat_map(|definition| definition.renderer.tiles())
+            .for_each(|tile| if let Tile::Animated(animation) = tile { animations.add(*animation); });
+        animations
+    }
+
+    /// Adds the given animation if it is not already in this list, returning its index.
+    pub fn add(&mut self, animation: TileAnimation) -> u32 {
+        match self.index(&animation) {
+            Some(index) => index,
+            None => { self.animations.push(animation); self.animations.len() as u32 - 1 }
+        }
+    }
+
+    /// Gets the index of the given animation in this list.
+    pub fn index(&self, animation: &TileAnimation) -> Option<u32> {
+        self.animations.iter().position(|other| other == animation).map(|index| index as u32)
+    }
+
+    /// The animations in this list.
+    pub fn animations(&self) -> &[TileAnimation] { &self.animations }
+
+    /// Converts this list into the raw animations and frames read by the terrain shader, resolving tile names with the given names.
+    pub(crate) fn to_raw(&self, names: &TileNames) -> (Vec<AnimationRaw>, Vec<FrameRaw>) {
+        let mut animations = Vec::with_capacity(self.animations.len());
+        let mut frames = Vec::new();
+        for animation in &self.animations {
+            animations.push(AnimationRaw {
+                start: frames.len() as u32,
+                count: animation.frames.len() as u32,
+                length: animation.length(),
+                interpolate: animation.interpolate as u32
+            });
+            frames.extend(animation.frames.iter().map(|frame| FrameRaw { layer: frame.tile.resolve(names) as u32, duration: frame.duration }));
+        }
+        (animations, frames)
+    }
+}
+
+/// A `TileAnimation` as it is read by the terrain shader.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct AnimationRaw {
+    pub start: u32,
+    pub count: u32,
+    pub length: f32,
+    pub interpolate: u32
+}
+
+/// An `AnimationFrame` as it is read by the terrain shader.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct FrameRaw {
+    pub layer: u32,
+    pub duration: f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRE: TileAnimation = TileAnimation { frames: &[AnimationFrame { tile: Tile::Index(4), duration: 0.5 }], interpolate: false };
+    const WATER: TileAnimation = TileAnimation {
+        frames: &[
+            AnimationFrame { tile: Tile::Named("water_0"), duration: 0.25 },
+            AnimationFrame { tile: Tile::Named("water_1"), duration: 0.75 }
+        ],
+        interpolate: true
+    };
+
+    #[test]
+    fn animations_are_packed_for_the_shader() {
+        let mut names = TileNames::new();
+        names.insert("water_0", 7);
+        names.insert("water_1", 8);
+
+        let mut animations = TileAnimations::new();
+        assert_eq!(animations.add(FIRE), 0);
+        assert_eq!(animations.add(WATER), 1);
+        assert_eq!(animations.add(FIRE), 0);
+
+        let (raw, frames) = animations.to_raw(&names);
+        assert_eq!(raw.len(), 2);
+        assert_eq!((raw[1].start, raw[1].count, raw[1].length, raw[1].interpolate), (1, 2, 1.0, 1));
+        assert_eq!((raw[0].start, raw[0].count, raw[0].interpolate), (0, 1, 0));
+
+        // the frames of each animation follow each other, with their layers resolved from the tile names
+        assert_eq!(frames.len(), 3);
+        assert_eq!((frames[0].layer, frames[0].duration), (4, 0.5));
+        assert_eq!(frames[raw[1].start as usize].layer, 7);
+        assert_eq!((frames[2].layer, frames[2].duration), (8, 0.75));
+    }
+}
@@ -0,0 +1,515 @@
+use std::{collections::HashMap, fmt::Display, fs};
+
+use forte_engine::{render::render_engine::RenderEngine, utils::files::Files};
+use image::RgbaImage;
+use wgpu::util::DeviceExt;
+
+use crate::terrain::{animations::{AnimationRaw, FrameRaw, TileAnimation, TileAnimations}, block_models::{BlockModelTile, BlockModels}, blocks::*};
+
+/// A reference to a tile in a `BlockAtlas`, either by its index or by the name it was given when the atlas was built.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tile {
+    /// The tile with the given index, which is its layer in the atlas.
+    Index(u16),
+    /// The tile with the given name.  See `BlockAtlasBuilder` documentation for more info.
+    Named(&'static str),
+    /// A tile that animates through a list of frames.  See `TileAnimation` documentation for more info.
+    Animated(&'static TileAnimation)
+}
+
+impl Tile {
+    /// Resolves this tile to its index in the atlas with the given names.  Animated tiles resolve to their first frame.
+    ///
+    /// Panics if this tile is named and the name is not in the atlas.  `BlockAtlasBuilder::build` checks every name used by the block definitions, so this can only happen with names not used by any block.
+    pub fn resolve(&self, names: &TileNames) -> u16 {
+        match self {
+            Self::Index(index) => *index,
+            Self::Named(name) => names.get(name).unwrap_or_else(|| panic!("Could not find block atlas tile {}!", name)),
+            Self::Animated(animation) => animation.frames.first().expect("Tile animations must have at least one frame!").tile.resolve(names)
+        }
+    }
+}
+
+/// The names of the tiles in a `BlockAtlas` mapped to their indices.
+#[derive(Debug, Clone, Default)]
+pub struct TileNames {
+    names: HashMap<String, u16>
+}
+
+impl TileNames {
+    /// Creates an empty set of tile names.
+    pub fn new() -> Self { Self::default() }
+
+    /// Gets the index of the tile with the given name.
+    pub fn get(&self, name: &str) -> Option<u16> { self.names.get(name).copied() }
+
+    /// Gives the tile at the given index the given name.
+    pub fn insert(&mut self, name: impl Into<String>, index: u16) { self.names.insert(name.into(), index); }
+
+    /// The number of named tiles.
+    pub fn len(&self) -> usize { self.names.len() }
+
+    /// Are there no named tiles?
+    pub fn is_empty(&self) -> bool { self.names.is_empty() }
+}
+
+/// The errors that can occur while building a `BlockAtlas`.
+#[derive(Debug)]
+pub enum AtlasError {
+    /// A file or directory could not be read.
+    Io(String, std::io::Error),
+    /// An image file could not be decoded.
+    Image(String, image::ImageError),
+    /// A tile image was not the atlas tile size.
+    WrongSize { name: String, expected: u32, width: u32, height: u32 },
+    /// Two tiles were given the same name.
+    DuplicateTile(String),
+    /// A block or block model referenced a tile name that is not in the atlas.
+    MissingTile { tile: String, user: String },
+    /// A block referenced a block model name that is not in the `BlockModels`.
+    MissingModel { model: String, user: String },
+    /// A `BlockRenderer::Connected` block used an animated tile, which connected blocks do not support.
+    AnimatedConnected(String)
+}
+
+impl Display for AtlasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "Could not read {}: {}", path, err),
+            Self::Image(path, err) => write!(f, "Could not decode tile image {}: {}", path, err),
+            Self::WrongSize { name, expected, width, height } => write!(f, "Tile {} is {}x{} pixels but the atlas tile size is {}x{}", name, width, height, expected, expected),
+            Self::DuplicateTile(name) => write!(f, "Tile {} was added to the atlas more than once", name),
+            Self::MissingTile { tile, user } => write!(f, "{} uses tile {} which is not in the atlas", user, tile),
+            Self::MissingModel { model, user } => write!(f, "{} uses block model {} which has not been loaded", user, model),
+            Self::AnimatedConnected(user) => write!(f, "{} is a connected block with an animated tile, but connected blocks can not be animated", user)
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+/// Builds a `BlockAtlas` from individually named tile images, so that blocks can reference tiles with `Tile::Named` instead of hand numbering an atlas image.
+///
+/// Example
+/// ```rust,ignore
+/// let mut builder = BlockAtlasBuilder::new(16);
+/// builder.load_dir("assets/blocks")?;            // adds "assets/blocks/grass_top.png" as the tile "grass_top"
+/// let atlas = builder.build::<Blocks, Material>(&engine, &models)?; // fails if a named tile used by `Blocks` does not exist
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlockAtlasBuilder {
+    tile_size: u32,
+    tiles: Vec<Vec<u8>>,
+    names: TileNames
+}
+
+impl BlockAtlasBuilder {
+    /// Creates a new empty builder where each tile is `tile_size` by `tile_size` pixels.
+    pub fn new(tile_size: u32) -> Self { Self { tile_size, tiles: Vec::new(), names: TileNames::new() } }
+
+    /// The names of the tiles added so far.
+    pub fn names(&self) -> &TileNames { &self.names }
+
+    /// Adds a tile from its RGBA8 pixels with the given name, returning its index.
+    pub fn add(&mut self, name: impl Into<String>, pixels: Vec<u8>) -> Result<u16, AtlasError> {
+        let name = name.into();
+        if self.names.get(&name).is_some() { return Err(AtlasError::DuplicateTile(name)) }
+        if pixels.len() != (self.tile_size * self.tile_size * 4) as usize {
+            let side = ((pixels.len() / 4) as f32).sqrt() as u32;
+            return Err(AtlasError::WrongSize { name, expected: self.tile_size, width: side, height: side })
+        }
+
+        let index = self.tiles.len() as u16;
+        self.tiles.push(pixels);
+        self.names.insert(name, index);
+        Ok(index)
+    }
+
+    /// Lays the tiles added so far out in one image with the given number of columns, left to right, then top to bottom like `BlockAtlas::load` expects, so the atlas can be saved for tools like `ObjExport`.
+    ///
+    /// Returns the image and the number of rows of tiles in it.
+    pub fn to_sheet(&self, columns: u32) -> (RgbaImage, u32) {
+        let columns = columns.max(1);
+        let rows = (self.tiles.len() as u32).div_ceil(columns).max(1);
+        let mut sheet = RgbaImage::new(columns * self.tile_size, rows * self.tile_size);
+        for (index, pixels) in self.tiles.iter().enumerate() {
+            let tile = RgbaImage::from_raw(self.tile_size, self.tile_size, pixels.clone()).unwrap();
+            let (column, row) = (index as u32 % columns, index as u32 / columns);
+            image::imageops::replace(&mut sheet, &tile, (column * self.tile_size) as i64, (row * self.tile_size) as i64);
+        }
+        (sheet, rows)
+    }
+
+    /// Adds a tile from the image file at the given path with the given name, returning its index.
+    pub fn add_image(&mut self, name: impl Into<String>, path: &str) -> Result<u16, AtlasError> {
+        let name = name.into();
+        let bytes = fs::read(path).map_err(|err| AtlasError::Io(path.to_string(), err))?;
+        let image = image::load_from_memory(&bytes).map_err(|err| AtlasError::Image(path.to_string(), err))?.to_rgba8();
+        if image.width() != self.tile_size || image.height() != self.tile_size {
+            return Err(AtlasError::WrongSize { name, expected: self.tile_size, width: image.width(), height: image.height() })
+        }
+        self.add(name, image.into_raw())
+    }
+
+    /// Adds every PNG image in the given directory as a tile named by its file name without the extension.  Tiles are added in order of their names so their indices are stable.
+    pub fn load_dir(&mut self, path: &str) -> Result<(), AtlasError> {
+        let entries = fs::read_dir(path).map_err(|err| AtlasError::Io(path.to_string(), err))?;
+        let mut files = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|err| AtlasError::Io(path.to_string(), err))?;
+            let file = entry.path();
+            if file.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")) { files.push(file); }
+        }
+        files.sort();
+
+        for file in files {
+            let name = file.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default().to_string();
+            self.add_image(name, file.to_str().unwrap_or_default())?;
+        }
+        Ok(())
+    }
+
+    /// Checks that every named tile used by the given block definitions and block models is in this atlas, and that every block model used by the definitions is loaded.  See `BlockModels::validate`.
+    pub fn validate<T: BlockDefinitions<M>, M: MaterialDef + 'static>(&self, models: &BlockModels) -> Result<(), AtlasError> {
+        models.validate::<T, M>()?;
+        for definition in T::DEFINITIONS {
+            // connected variants are offsets from a single tile, which animations do not have
+            if let BlockRenderer::Connected(Tile::Animated(_), _) = definition.renderer { return Err(AtlasError::AnimatedConnected(format!("Block {:?}", definition.material))) }
+
+            for tile in definition.renderer.tiles() {
+                // check the frames of animated tiles as well as the tile itself
+                let frames = match tile { Tile::Animated(animation) => animation.frames.iter().map(|frame| frame.tile).collect(), tile => vec![tile] };
+                for tile in frames {
+                    if let Tile::Named(name) = tile {
+                        if self.names.get(name).is_none() { return Err(AtlasError::MissingTile { tile: name.to_string(), user: format!("Block {:?}", definition.material) }) }
+                    }
+                }
+            }
+        }
+
+        for (model_name, model) in models.iter() {
+            for element in &model.elements {
+                for face in element.faces.iter().flatten() {
+                    if let BlockModelTile::Named(name) = &face.tile {
+                        if self.names.get(name).is_none() { return Err(AtlasError::MissingTile { tile: name.clone(), user: format!("Block model {}", model_name) }) }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the atlas from the added tiles, after checking the given block definitions and block models with `validate` so that every tile and model name resolves while meshing.
+    ///
+    /// Arguments:
+    /// * engine: &RenderEngine - The render engine to create the texture with.
+    /// * models: &BlockModels - The block models that will be used with this atlas.
+    pub fn build<T: BlockDefinitions<M>, M: MaterialDef + 'static>(self, engine: &RenderEngine, models: &BlockModels) -> Result<BlockAtlas, AtlasError> {
+        self.validate::<T, M>(models)?;
+        let mut atlas = BlockAtlas::from_tiles(engine, self.tile_size, &self.tiles);
+        atlas.names = self.names;
+        Ok(atlas)
+    }
+}
+
+/// A block texture atlas where each tile is stored in its own layer of a texture array.
+///
+/// Since tiles never share a layer, linear filtering and mipmaps cannot bleed neighbouring tiles into each other.
+#[derive(Debug)]
+pub struct BlockAtlas {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub bind_group: wgpu::BindGroup,
+    /// The width and height of each tile in pixels.
+    pub tile_size: u32,
+    /// The number of tiles, and therefore layers, in this atlas.
+    pub tile_count: u32,
+    /// The names of the tiles in this atlas, if it was made with a `BlockAtlasBuilder`.
+    pub names: TileNames,
+    /// The animated tiles that can be used with this atlas.  Set these with `set_animations`.
+    pub animations: TileAnimations,
+    animation_buffer: wgpu::Buffer,
+    frame_buffer: wgpu::Buffer,
+    time_buffer: wgpu::Buffer
+}
+
+impl BlockAtlas {
+    /// The bind group layout of a block atlas.  Binding 0 is a `texture_2d_array<f32>` and binding 1 is its sampler.
+    /// Bindings 2 and 3 are the storage buffers of tile animations and their frames, and binding 4 is a uniform with the animation time in seconds in its first component.
+    pub const BIND_LAYOUT: wgpu::BindGroupLayoutDescriptor<'static> = wgpu::BindGroupLayoutDescriptor {
+        label: Some("block_atlas_bind_group_layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Storage { read_only: true }, has_dynamic_offset: false, min_binding_size: None },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Storage { read_only: true }, has_dynamic_offset: false, min_binding_size: None },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+                count: None,
+            },
+        ],
+    };
+
+    /// Loads a block atlas from a single image where tiles are laid out left to right, then top to bottom.  The index of each tile is its layer.
+    ///
+    /// Arguments:
+    /// * engine: &RenderEngine - The render engine to create the texture with.
+    /// * path: &str - The path to the atlas image.
+    /// * tile_size: u32 - The width and height of each tile in pixels.
+    pub fn load(engine: &RenderEngine, path: &str, tile_size: u32) -> Self {
+        let bytes = Files::load_bytes(path).unwrap_or_else(|_| panic!("Could not load block atlas {}!", path));
+        let image = image::load_from_memory(&bytes).unwrap_or_else(|err| panic!("Could not decode block atlas {}: {}", path, err)).to_rgba8();
+
+        // split the image into tiles
+        let columns = image.width() / tile_size;
+        let rows = image.height() / tile_size;
+        let mut tiles = Vec::with_capacity((columns * rows) as usize);
+        for row in 0 .. rows {
+            for column in 0 .. columns {
+                let tile = image::imageops::crop_imm(&image, column * tile_size, row * tile_size, tile_size, tile_size).to_image();
+                tiles.push(tile.into_raw());
+            }
+        }
+
+        Self::from_tiles(engine, tile_size, &tiles)
+    }
+
+    /// Creates a block atlas from a list of tiles, each of which is the RGBA8 pixels of a `tile_size` by `tile_size` image.  The index of each tile is its layer.
+    pub fn from_tiles(engine: &RenderEngine, tile_size: u32, tiles: &[Vec<u8>]) -> Self {
+        let tile_count = tiles.len().max(1) as u32;
+        let mip_level_count = u32::BITS - tile_size.leading_zeros();
+
+        // create texture array
+        let texture = engine.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("block_atlas"),
+            size: wgpu::Extent3d { width: tile_size, height: tile_size, depth_or_array_layers: tile_count },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[]
+        });
+
+        // upload each tile and its mipmaps
+        for (layer, tile) in tiles.iter().enumerate() {
+            if tile.len() != (tile_size * tile_size * 4) as usize { panic!("Block atlas tile {} is not {}x{} RGBA8 pixels!", layer, tile_size, tile_size); }
+            let mut level = tile.clone();
+            let mut size = tile_size;
+            for mip_level in 0 .. mip_level_count {
+                engine.queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level,
+                        origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                        aspect: wgpu::TextureAspect::All
+                    },
+                    &level,
+                    wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * size), rows_per_image: Some(size) },
+                    wgpu::Extent3d { width: size, height: size, depth_or_array_layers: 1 }
+                );
+                level = downsample(&level, size);
+                size = (size / 2).max(1);
+            }
+        }
+
+        // create view, sampler and bind group
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = engine.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // create animation buffers with no animations
+        let animations = TileAnimations::new();
+        let (animation_buffer, frame_buffer) = Self::create_animation_buffers(engine, &animations, &TileNames::new());
+        let time_buffer = engine.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("block_atlas_time"),
+            contents: bytemuck::cast_slice(&[0.0f32; 4]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+        });
+        let bind_group = Self::create_bind_group(engine, &view, &sampler, &animation_buffer, &frame_buffer, &time_buffer);
+
+        Self { texture, view, sampler, bind_group, tile_size, tile_count, names: TileNames::new(), animations, animation_buffer, frame_buffer, time_buffer }
+    }
+
+    /// Sets the animated tiles that can be used with this atlas.  Chunks must be remeshed after this is called.
+    pub fn set_animations(&mut self, engine: &RenderEngine, animations: TileAnimations) {
+        let (animation_buffer, frame_buffer) = Self::create_animation_buffers(engine, &animations, &self.names);
+        self.bind_group = Self::create_bind_group(engine, &self.view, &self.sampler, &animation_buffer, &frame_buffer, &self.time_buffer);
+        self.animation_buffer = animation_buffer;
+        self.frame_buffer = frame_buffer;
+        self.animations = animations;
+    }
+
+    /// Updates the animation time of this atlas to the time since the engine started.  Call this once per frame.
+    pub fn update(&self, engine: &RenderEngine) {
+        engine.queue.write_buffer(&self.time_buffer, 0, bytemuck::cast_slice(&[engine.time_since_start, 0.0, 0.0, 0.0]));
+    }
+
+    fn create_animation_buffers(engine: &RenderEngine, animations: &TileAnimations, names: &TileNames) -> (wgpu::Buffer, wgpu::Buffer) {
+        // storage buffers cannot be empty, so pad them with a default entry
+        let (mut raw_animations, mut raw_frames) = animations.to_raw(names);
+        if raw_animations.is_empty() { raw_animations.push(AnimationRaw::default()); }
+        if raw_frames.is_empty() { raw_frames.push(FrameRaw::default()); }
+
+        let animation_buffer = engine.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("block_atlas_animations"),
+            contents: bytemuck::cast_slice(&raw_animations),
+            usage: wgpu::BufferUsages::STORAGE
+        });
+        let frame_buffer = engine.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("block_atlas_frames"),
+            contents: bytemuck::cast_slice(&raw_frames),
+            usage: wgpu::BufferUsages::STORAGE
+        });
+        (animation_buffer, frame_buffer)
+    }
+
+    fn create_bind_group(
+        engine: &RenderEngine, 
+        view: &wgpu::TextureView, 
+        sampler: &wgpu::Sampler, 
+        animation_buffer: &wgpu::Buffer, 
+        frame_buffer: &wgpu::Buffer, 
+        time_buffer: &wgpu::Buffer
+    ) -> wgpu::BindGroup {
+        engine.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("block_atlas_bind_group"),
+            layout: &engine.device.create_bind_group_layout(&Self::BIND_LAYOUT),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(sampler) },
+                wgpu::BindGroupEntry { binding: 2, resource: animation_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: frame_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 4, resource: time_buffer.as_entire_binding() }
+            ]
+        })
+    }
+
+    /// Binds this atlas to the given render pass at the given bind group index.
+    pub fn bind<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, index: u32) {
+        pass.set_bind_group(index, &self.bind_group, &[]);
+    }
+}
+
+// Halves the size of a square RGBA8 image by averaging each 2x2 block of pixels.
+// The atlas is sRGB, so colours are averaged in linear space to keep distant tiles from darkening, while alpha is averaged as is.
+fn downsample(pixels: &[u8], size: u32) -> Vec<u8> {
+    let new_size = (size / 2).max(1);
+    let mut result = vec![0; (new_size * new_size * 4) as usize];
+    for y in 0 .. new_size {
+        for x in 0 .. new_size {
+            for channel in 0 .. 4 {
+                let mut total = 0.0;
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (x * 2 + dx).min(size - 1);
+                    let sy = (y * 2 + dy).min(size - 1);
+                    let value = pixels[((sy * size + sx) * 4 + channel) as usize];
+                    total += if channel == 3 { value as f32 / 255.0 } else { srgb_to_linear(value) };
+                }
+                let average = total / 4.0;
+                result[((y * new_size + x) * 4 + channel) as usize] = if channel == 3 { (average * 255.0).round() as u8 } else { linear_to_srgb(average) };
+            }
+        }
+    }
+    result
+}
+
+// Converts an sRGB encoded colour channel to a linear value from 0 to 1.
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+// Converts a linear value from 0 to 1 to an sRGB encoded colour channel.
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.0031308 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 };
+    (encoded * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::define_blocks_materials;
+
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        AIR => { transparent: true, renderer: BlockRenderer::None },
+        GRASS => { transparent: false, renderer: BlockRenderer::Standard(Tile::Named("grass_top"), Tile::Index(0), Tile::Named("grass_side"), Tile::Named("grass_side"), Tile::Named("grass_side"), Tile::Named("grass_side")) }
+    ]);
+
+    #[test]
+    fn validate_finds_missing_tiles() {
+        let mut builder = BlockAtlasBuilder::new(2);
+        builder.add("grass_top", vec![0; 16]).unwrap();
+        let models = BlockModels::new();
+        assert!(matches!(builder.validate::<Blocks, Material>(&models), Err(AtlasError::MissingTile { tile, .. }) if tile == "grass_side"));
+        builder.add("grass_side", vec![0; 16]).unwrap();
+        assert!(builder.validate::<Blocks, Material>(&models).is_ok());
+        assert!(matches!(builder.add("grass_side", vec![0; 16]), Err(AtlasError::DuplicateTile(_))));
+        assert!(matches!(builder.add("dirt", vec![0; 4]), Err(AtlasError::WrongSize { .. })));
+    }
+
+    #[test]
+    fn sheets_lay_tiles_out_in_rows() {
+        let mut builder = BlockAtlasBuilder::new(2);
+        for value in 1 ..= 3 { builder.add(format!("tile_{}", value), vec![value; 16]).unwrap(); }
+        let (sheet, rows) = builder.to_sheet(2);
+        assert_eq!((sheet.width(), sheet.height(), rows), (4, 4, 2));
+        assert_eq!(sheet.get_pixel(1, 1).0, [1; 4]);
+        assert_eq!(sheet.get_pixel(2, 0).0, [2; 4]);
+        assert_eq!(sheet.get_pixel(0, 3).0, [3; 4]);
+        assert_eq!(sheet.get_pixel(3, 3).0, [0; 4]);
+    }
+
+    #[test]
+    fn downsample_averages_colours_in_linear_space() {
+        // a black and white checker with alpha 0 and 255
+        let pixels = [[0, 0, 0, 0], [255, 255, 255, 255], [255, 255, 255, 255], [0, 0, 0, 0]].concat();
+        let result = downsample(&pixels, 2);
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0], 188);
+        assert_eq!(result[3], 128);
+    }
+
+    #[test]
+    fn srgb_round_trips() {
+        for value in 0 ..= 255u8 { assert_eq!(linear_to_srgb(srgb_to_linear(value)), value); }
+    }
+}
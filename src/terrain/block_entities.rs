@@ -0,0 +1,170 @@
+use std::{any::Any, collections::HashMap, fmt::Debug};
+
+use cgmath::*;
+use serde::*;
+
+use crate::terrain::states::BlockState;
+
+/// The callback type a `BlockDef` can provide to create a block entity whenever its block is placed.
+pub type BlockEntityFactory = fn(BlockState) -> Box<dyn BlockEntity>;
+
+/// Lets a `dyn BlockEntity` be downcast to its concrete type.  This is implemented for every type automatically.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl <T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+/// Extra state stored for a single block, like the contents of a chest or the text of a sign.
+///
+/// Block entities are created by the `BlockEntityFactory` of a `BlockDef` when its block is set in a chunk, and destroyed when the block is replaced with a different material.
+///
+/// Example
+/// ```rust,ignore
+/// #[derive(Debug, Default)]
+/// struct Sign { text: String }
+///
+/// impl BlockEntity for Sign {
+///     fn save(&self) -> serde_json::Value { serde_json::Value::String(self.text.clone()) }
+///     fn load(&mut self, value: serde_json::Value) { self.text = value.as_str().unwrap_or_default().to_string(); }
+/// }
+/// ```
+pub trait BlockEntity: AsAny + Debug {
+    /// Saves this block entity so it can be restored with `load`.  Returns null by default, meaning nothing is saved.
+    fn save(&self) -> serde_json::Value { serde_json::Value::Null }
+
+    /// Restores this block entity from a value returned by `save`.  This is called on a block entity that was just created by its factory.  Does nothing by default.
+    fn load(&mut self, _value: serde_json::Value) {}
+}
+
+/// A saved block entity, from `BlockEntities::save`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedBlockEntity {
+    /// The local position of the block entity in its chunk.
+    pub position: [usize; 3],
+    /// The value returned by `BlockEntity::save`.
+    pub data: serde_json::Value
+}
+
+/// The block entities of a chunk, keyed by the local position of their block.
+#[derive(Debug, Default)]
+pub struct BlockEntities {
+    entities: HashMap<Vector3<usize>, Box<dyn BlockEntity>>
+}
+
+impl BlockEntities {
+    /// Creates an empty set of block entities.
+    pub fn new() -> Self { Self::default() }
+
+    pub fn len(&self) -> usize { self.entities.len() }
+    pub fn is_empty(&self) -> bool { self.entities.is_empty() }
+    pub fn contains(&self, position: Vector3<usize>) -> bool { self.entities.contains_key(&position) }
+
+    /// Gets the block entity at the given position.
+    pub fn get_dyn(&self, position: Vector3<usize>) -> Option<&dyn BlockEntity> { self.entities.get(&position).map(|entity| entity.as_ref()) }
+
+    /// Gets the block entity at the given position mutably.
+    pub fn get_dyn_mut(&mut self, position: Vector3<usize>) -> Option<&mut (dyn BlockEntity + 'static)> { self.entities.get_mut(&position).map(|entity| entity.as_mut()) }
+
+    /// Gets the block entity at the given position if it has the type `E`.
+    pub fn get<E: BlockEntity + 'static>(&self, position: Vector3<usize>) -> Option<&E> { self.get_dyn(position)?.as_any().downcast_ref() }
+
+    /// Gets the block entity at the given position mutably if it has the type `E`.
+    pub fn get_mut<E: BlockEntity + 'static>(&mut self, position: Vector3<usize>) -> Option<&mut E> { self.get_dyn_mut(position)?.as_any_mut().downcast_mut() }
+
+    /// Sets the block entity at the given position, returning the block entity that was replaced.
+    pub fn insert(&mut self, position: Vector3<usize>, entity: Box<dyn BlockEntity>) -> Option<Box<dyn BlockEntity>> { self.entities.insert(position, entity) }
+
+    /// Removes the block entity at the given position, returning it.
+    pub fn remove(&mut self, position: Vector3<usize>) -> Option<Box<dyn BlockEntity>> { self.entities.remove(&position) }
+
+    /// Iterates over every block entity and its position in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (Vector3<usize>, &dyn BlockEntity)> { self.entities.iter().map(|(position, entity)| (*position, entity.as_ref())) }
+
+    /// Iterates mutably over every block entity and its position in no particular order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Vector3<usize>, &mut (dyn BlockEntity + 'static))> { self.entities.iter_mut().map(|(position, entity)| (*position, entity.as_mut())) }
+
+    /// Saves every block entity with `BlockEntity::save`, sorted by position.  Block entities that save null are left out.
+    pub fn save(&self) -> Vec<SavedBlockEntity> {
+        let mut saved: Vec<SavedBlockEntity> = self.entities.iter()
+            .map(|(position, entity)| SavedBlockEntity { position: (*position).into(), data: entity.save() })
+            .filter(|saved| !saved.data.is_null())
+            .collect();
+        saved.sort_by_key(|saved| saved.position);
+        saved
+    }
+
+    /// Restores block entities saved with `save` into the block entities that already exist at their positions.  Saved block entities with no block entity at their position are ignored.
+    pub fn load(&mut self, saved: Vec<SavedBlockEntity>) {
+        for saved in saved {
+            if let Some(entity) = self.entities.get_mut(&Vector3::from(saved.position)) { entity.load(saved.data); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{define_blocks_materials, terrain::{atlas::Tile, blocks::*, chunk::Chunk}};
+
+    #[derive(Debug, Default)]
+    struct Sign { text: String }
+
+    impl BlockEntity for Sign {
+        fn save(&self) -> serde_json::Value { if self.text.is_empty() { serde_json::Value::Null } else { serde_json::Value::String(self.text.clone()) } }
+        fn load(&mut self, value: serde_json::Value) { self.text = value.as_str().unwrap_or_default().to_string(); }
+    }
+
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        AIR => { transparent: true, renderer: BlockRenderer::None },
+        STONE => { transparent: false, renderer: BlockRenderer::Standard(Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0)) },
+        SIGN => { transparent: true, renderer: BlockRenderer::BlockEntity, entity: |_| Box::new(Sign::default()) }
+    ]);
+
+    type TestChunk = Chunk<Blocks, Material, 4, 4, 4>;
+
+    #[test]
+    fn setting_blocks_creates_and_destroys_entities() {
+        let mut chunk = TestChunk::empty(0);
+        let position = Vector3::new(1, 2, 3);
+        chunk.set(position, Material::SIGN, 0);
+        assert!(chunk.block_entity::<Sign>(position).is_some());
+        chunk.block_entity_mut::<Sign>(position).unwrap().text = "Hello".to_string();
+
+        // setting the same material again keeps the entity, only its data changes
+        chunk.set(position, Material::SIGN, 1);
+        assert_eq!(chunk.block_entity::<Sign>(position).unwrap().text, "Hello");
+
+        // a different material destroys it
+        chunk.set(position, Material::STONE, 0);
+        assert!(chunk.block_entity::<Sign>(position).is_none());
+        assert!(chunk.block_entities().is_empty());
+    }
+
+    #[test]
+    fn entities_are_saved_and_loaded() {
+        let mut chunk = TestChunk::empty(0);
+        for (x, text) in [(2, "second"), (0, "first"), (1, "")] {
+            chunk.set(Vector3::new(x, 0, 0), Material::SIGN, 0);
+            chunk.block_entity_mut::<Sign>(Vector3::new(x, 0, 0)).unwrap().text = text.to_string();
+        }
+
+        // entities that save null are left out, and the rest are sorted by position
+        let saved = chunk.save_block_entities();
+        assert_eq!(saved, vec![
+            SavedBlockEntity { position: [0, 0, 0], data: serde_json::json!("first") },
+            SavedBlockEntity { position: [2, 0, 0], data: serde_json::json!("second") }
+        ]);
+
+        // loading only fills entities that exist in the new chunk
+        let mut loaded = TestChunk::empty(1);
+        loaded.set(Vector3::new(0, 0, 0), Material::SIGN, 0);
+        loaded.load_block_entities(saved);
+        assert_eq!(loaded.block_entity::<Sign>(Vector3::new(0, 0, 0)).unwrap().text, "first");
+        assert_eq!(loaded.block_entities().len(), 1);
+    }
+}
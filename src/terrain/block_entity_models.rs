@@ -0,0 +1,119 @@
+use std::marker::PhantomData;
+
+use cgmath::*;
+use forte_engine::{primitives::textures::Texture, render::render_engine::RenderEngine, utils::resources::Handle};
+
+use crate::{models::{cubes::CubeModel, data::{CubeInstance, CubeModelBone, CubeModelData}, CubeEngine, DrawCubes}, terrain::{blocks::*, chunk::Chunk}};
+
+// The model drawn for every block of one material, and the batch of its instances across all chunks.
+#[derive(Debug)]
+struct BlockEntityModel {
+    material: u16,
+    transform: Matrix4<f32>,
+    rotation: Quaternion<f32>,
+    texture: Handle<Texture>,
+    bone: CubeModelBone,
+    data: Option<CubeModelData>
+}
+
+/// Draws a `CubeModel` for every block with a `BlockRenderer::BlockEntity` renderer, like chests, doors and signs.
+///
+/// Each material is given one model, and every block of that material across all chunks is drawn in one batch.
+/// Models are placed in the 1x1x1 space of their block, with the models transform as the offset inside the block, and are rotated around the center of the block by the "facing" or "axis" property of the blocks `BlockState`, like `BlockRenderer::Oriented`.
+///
+/// Example
+/// ```rust,ignore
+/// let mut models = BlockEntityModels::new();
+/// models.register(Material::CHEST, &SBFile::load("assets/chest.json").as_model(&mut engine));
+///
+/// // each frame
+/// models.update(&engine, streamer.chunks().map(|(_, chunk)| chunk));
+/// pass.prepare_cube_engine(&cube_engine, &camera);
+/// models.draw(&engine, &cube_engine, &mut pass);
+/// ```
+#[derive(Debug)]
+pub struct BlockEntityModels<M: MaterialDef + 'static> {
+    models: Vec<BlockEntityModel>,
+    phantom_material: PhantomData<M>
+}
+
+impl <M: MaterialDef + 'static> Default for BlockEntityModels<M> {
+    fn default() -> Self { Self::new() }
+}
+
+impl <M: MaterialDef + 'static> BlockEntityModels<M> {
+    /// Creates a renderer with no models.
+    pub fn new() -> Self { Self { models: Vec::new(), phantom_material: PhantomData } }
+
+    /// Sets the model drawn for blocks of the given material, replacing any model it had before.  The bone and texture of the model are copied, and its transform is used as its offset in the block.
+    pub fn register(&mut self, material: M, model: &CubeModel) {
+        let material: u16 = material.into();
+        self.models.retain(|model| model.material != material);
+        self.models.push(BlockEntityModel {
+            material,
+            transform: model.transform.to_mat(),
+            rotation: model.transform.rotation,
+            texture: Handle::<Texture> { hash: model.texture.hash, data: PhantomData },
+            bone: model.bone.clone(),
+            data: None
+        });
+    }
+
+    /// Gets the root bone of the model for the given material, so it can be animated with an `AnimController`.  Changes are drawn after the next `update`.
+    pub fn bone_mut(&mut self, material: M) -> Option<&mut CubeModelBone> {
+        let material: u16 = material.into();
+        self.models.iter_mut().find(|model| model.material == material).map(|model| &mut model.bone)
+    }
+
+    /// Rebuilds the batches of model instances from the block entity blocks of the given chunks.  Blocks are found when chunks are meshed, see `Chunk::block_entity_blocks`.
+    ///
+    /// Arguments:
+    /// * engine: &RenderEngine - The engine used to update the instance buffers.
+    /// * chunks: impl IntoIterator<Item = &Chunk<T, M, X, Y, Z>> - The chunks to draw the block entities of.
+    pub fn update<'c, T: BlockDefinitions<M> + 'c, const X: usize, const Y: usize, const Z: usize>(&mut self, engine: &RenderEngine, chunks: impl IntoIterator<Item = &'c Chunk<T, M, X, Y, Z>>) {
+        let mut instances: Vec<Vec<CubeInstance>> = self.models.iter().map(|_| Vec::new()).collect();
+
+        // look up the texture size of each model once rather than for every instance
+        let texture_sizes: Vec<Vector2<f32>> = self.models.iter().map(|model| {
+            let texture = engine.texture(&model.texture);
+            Vector2 { x: texture.texture.width() as f32, y: texture.texture.height() as f32 }
+        }).collect();
+
+        for chunk in chunks {
+            let chunk_matrix = chunk.transform.to_mat();
+            for location in chunk.block_entity_blocks() {
+                let material: u16 = chunk.get(*location).into();
+                let index = match self.models.iter().position(|model| model.material == material) { Some(index) => index, None => continue };
+                let model = &self.models[index];
+
+                // place the model in its block, rotated around the center of the block
+                let rotation = chunk.get_state(*location).rotation();
+                let center = location.cast::<f32>().unwrap() + Vector3::from_value(0.5);
+                let matrix = chunk_matrix
+                    * Matrix4::from_translation(center)
+                    * Matrix4::from(rotation)
+                    * Matrix4::from_translation(Vector3::from_value(-0.5))
+                    * model.transform;
+                let rotation = chunk.transform.rotation * Quaternion::from(rotation) * model.rotation;
+
+                CubeModel::render_bones_matrix(&mut instances[index], &matrix, rotation, &model.bone, texture_sizes[index], 16.0);
+            }
+        }
+
+        // update each batch, recreating its buffer if the number of instances changed
+        for (model, instances) in self.models.iter_mut().zip(instances) {
+            match model.data.as_ref() {
+                _ if instances.is_empty() => model.data = None,
+                Some(data) if data.size as usize == instances.len() => data.update(engine, instances),
+                _ => model.data = Some(CubeModelData::new(engine, instances))
+            }
+        }
+    }
+
+    /// Draws every batch of models.  The `CubeEngine` must already be prepared on the pass with `DrawCubes::prepare_cube_engine`.
+    pub fn draw<'a, 'b>(&'b self, engine: &'b RenderEngine, cube_engine: &'b CubeEngine, pass: &mut wgpu::RenderPass<'a>) where 'b: 'a {
+        for model in self.models.iter() {
+            if let Some(data) = model.data.as_ref() { pass.draw_cubes_raw(engine, cube_engine.mesh(), &model.texture, data); }
+        }
+    }
+}
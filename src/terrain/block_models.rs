@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use cgmath::*;
+use forte_engine::{math::vec::VecExt, primitives::vertices::Vertex, utils::files::Files};
+use serde::*;
+
+use crate::terrain::{atlas::{AtlasError, TileNames}, blocks::*, lookup, states::{Axis, Direction}};
+
+/// A data driven model for blocks that are not a full cube, made from a list of boxes.
+///
+/// All positions and UVs are in pixels, where a block is 16 pixels wide, to match the format of common block modelling tools.
+///
+/// Example
+/// ```json
+/// {
+///     "elements": [
+///         {
+///             "from": [6, 0, 6],
+///             "to": [10, 16, 10],
+///             "faces": {
+///                 "above": { "tile": "post_top", "uv": [6, 6, 10, 10], "cullface": "above" },
+///                 "below": { "tile": "post_top", "uv": [6, 6, 10, 10], "cullface": "below" },
+///                 "north": { "tile": 3 },
+///                 "south": { "tile": 3 },
+///                 "east": { "tile": 3 },
+///                 "west": { "tile": 3 }
+///             }
+///         }
+///     ]
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockModel {
+    pub elements: Vec<BlockModelBox>
+}
+
+/// A single box of a `BlockModel`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockModelBox {
+    /// The minimum corner of this box in pixels.
+    pub from: [f32; 3],
+    /// The maximum corner of this box in pixels.
+    pub to: [f32; 3],
+    /// An optional rotation of this box.
+    #[serde(default)]
+    pub rotation: Option<BlockModelRotation>,
+    /// The faces of this box to render.  Faces that are not given are not rendered.
+    pub faces: BlockModelFaces
+}
+
+/// A rotation of a `BlockModelBox` around a point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockModelRotation {
+    /// The point to rotate around in pixels.
+    pub origin: [f32; 3],
+    /// The axis to rotate around.
+    pub axis: Axis,
+    /// The angle to rotate by in degrees.
+    pub angle: f32
+}
+
+/// The faces of a `BlockModelBox` in the order above, below, north, south, east, west.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockModelFaces {
+    #[serde(default)] pub above: Option<BlockModelFace>,
+    #[serde(default)] pub below: Option<BlockModelFace>,
+    #[serde(default)] pub north: Option<BlockModelFace>,
+    #[serde(default)] pub south: Option<BlockModelFace>,
+    #[serde(default)] pub east: Option<BlockModelFace>,
+    #[serde(default)] pub west: Option<BlockModelFace>
+}
+
+impl BlockModelFaces {
+    /// Iterates the faces in the order above, below, north, south, east, west.
+    pub fn iter(&self) -> impl Iterator<Item = &Option<BlockModelFace>> {
+        [&self.above, &self.below, &self.north, &self.south, &self.east, &self.west].into_iter()
+    }
+}
+
+/// A reference to a tile in a `BlockAtlas` from a block model, either by index or by name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BlockModelTile {
+    Index(u16),
+    Named(String)
+}
+
+impl BlockModelTile {
+    /// Resolves this tile to its index in the atlas with the given names.  Panics if the name is not in the atlas.
+    pub fn resolve(&self, names: &TileNames) -> u16 {
+        match self {
+            Self::Index(index) => *index,
+            Self::Named(name) => names.get(name).unwrap_or_else(|| panic!("Could not find block atlas tile {}!", name))
+        }
+    }
+}
+
+/// A single face of a `BlockModelBox`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockModelFace {
+    /// The tile to texture this face with, either as an atlas index or a tile name.
+    pub tile: BlockModelTile,
+    /// The area of the tile to use in pixels as min u, min v, max u, max v.  If not given, the area is taken from the position of the face in the block.
+    #[serde(default)]
+    pub uv: Option<[f32; 4]>,
+    /// If given, this face is only rendered when the block in this direction is transparent.
+    #[serde(default)]
+    pub cullface: Option<Direction>
+}
+
+impl BlockModel {
+    /// Loads a block model from a JSON file at the given path.
+    pub fn load(path: &str) -> Self {
+        let bytes = Files::load_bytes(path).unwrap_or_else(|_| panic!("Could not load block model file {}!", path));
+        let str = String::from_utf8(bytes).unwrap_or_else(|_| panic!("Block model file {} is not valid UTF-8!", path));
+        Self::from_json(&str).unwrap_or_else(|err| panic!("Could not parse block model file {}: {}", path, err))
+    }
+
+    /// Parses a block model from a JSON string.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> { serde_json::from_str(json) }
+
+    /// Renders this model into a list of vertices in a 1x1x1 block space, with the texture coordinates local to each faces tile.
+    ///
+    /// Arguments:
+    /// * is_transparent: impl Fn(Direction) -> bool - Returns if the block in the given direction is transparent, used for cullfaces.
+    ///
+    /// Returns a list of (tile, vertices) for each rendered face.
+    pub fn render(&self, is_transparent: impl Fn(Direction) -> bool) -> Vec<(&BlockModelTile, Vec<Vertex>)> {
+        let mut result = Vec::new();
+        for element in &self.elements {
+            let from = Vector3::from(element.from) / 16.0;
+            let to = Vector3::from(element.to) / 16.0;
+            let faces = [
+                (&element.faces.above, Direction::Above, &lookup::CUBE_TOP),
+                (&element.faces.below, Direction::Below, &lookup::CUBE_BOTTOM),
+                (&element.faces.north, Direction::North, &lookup::CUBE_NORTH),
+                (&element.faces.south, Direction::South, &lookup::CUBE_SOUTH),
+                (&element.faces.east, Direction::East, &lookup::CUBE_EAST),
+                (&element.faces.west, Direction::West, &lookup::CUBE_WEST)
+            ];
+
+            for (face, direction, template) in faces {
+                // skip faces that do not exist or are culled
+                let face = match face { Some(face) => face, None => continue };
+                if face.cullface.is_some_and(|cull| !is_transparent(cull)) { continue }
+
+                // scale the template face to the box and generate texture coordinates from the position on the face
+                let mut vertices = template.to_vec();
+                vertices.for_each_mut(|vertex| {
+                    let unit = Vector3::from(vertex.position);
+                    let position = from + (to - from).mul_element_wise(unit);
+                    vertex.position = position.into();
+
+                    let (u, v) = face_uv(direction, position);
+                    vertex.tex_coords = match face.uv {
+                        Some(uv) => {
+                            let (from_u, from_v) = face_uv(direction, from);
+                            let (to_u, to_v) = face_uv(direction, to);
+                            let (min_u, max_u) = (from_u.min(to_u), from_u.max(to_u));
+                            let (min_v, max_v) = (from_v.min(to_v), from_v.max(to_v));
+                            let local_u = if max_u != min_u { (u - min_u) / (max_u - min_u) } else { 0.0 };
+                            let local_v = if max_v != min_v { (v - min_v) / (max_v - min_v) } else { 0.0 };
+                            [(uv[0] + (uv[2] - uv[0]) * local_u) / 16.0, (uv[1] + (uv[3] - uv[1]) * local_v) / 16.0]
+                        },
+                        None => [u, v]
+                    };
+                });
+
+                // rotate the box if necessary
+                if let Some(rotation) = &element.rotation {
+                    let origin = Vector3::from(rotation.origin) / 16.0;
+                    let matrix = match rotation.axis {
+                        Axis::X => Matrix3::from_angle_x(Deg(rotation.angle)),
+                        Axis::Y => Matrix3::from_angle_y(Deg(rotation.angle)),
+                        Axis::Z => Matrix3::from_angle_z(Deg(rotation.angle))
+                    };
+                    vertices.for_each_mut(|vertex| {
+                        vertex.position = (matrix * (Vector3::from(vertex.position) - origin) + origin).into();
+                        vertex.normal = (matrix * Vector3::from(vertex.normal)).into();
+                    });
+                }
+
+                result.push((&face.tile, vertices));
+            }
+        }
+        result
+    }
+}
+
+// The texture coordinates of a position on a face with the given direction, so that textures appear upright when viewed from outside the block.
+pub(crate) fn face_uv(direction: Direction, position: Vector3<f32>) -> (f32, f32) {
+    let (u, v) = match direction {
+        Direction::Above => (position.x, 1.0 - position.z),
+        Direction::Below => (position.x, position.z),
+        Direction::North => (1.0 - position.x, 1.0 - position.y),
+        Direction::South => (position.x, 1.0 - position.y),
+        Direction::East => (position.z, 1.0 - position.y),
+        Direction::West => (1.0 - position.z, 1.0 - position.y)
+    };
+    (u.clamp(0.0, 1.0), v.clamp(0.0, 1.0))
+}
+
+/// A registry of named `BlockModel`s that `BlockRenderer::Model` blocks reference.
+#[derive(Debug, Default)]
+pub struct BlockModels {
+    models: HashMap<String, BlockModel>
+}
+
+impl BlockModels {
+    /// Creates a new empty registry.
+    pub fn new() -> Self { Self::default() }
+
+    /// Adds the given model to this registry with the given name, replacing any model with the same name.
+    pub fn insert(&mut self, name: impl Into<String>, model: BlockModel) { self.models.insert(name.into(), model); }
+
+    /// Loads a model from a JSON file at the given path and adds it to this registry with the given name.
+    pub fn load(&mut self, name: impl Into<String>, path: &str) { self.insert(name, BlockModel::load(path)); }
+
+    /// Gets the model with the given name.
+    pub fn get(&self, name: &str) -> Option<&BlockModel> { self.models.get(name) }
+
+    /// Checks that every block model used by a `BlockRenderer::Model` in the given block definitions is in this registry.  `BlockAtlasBuilder::validate` also runs this.
+    /// Blocks whose model is missing are not drawn, so run this after loading models to catch typos in model names.
+    pub fn validate<T: BlockDefinitions<M>, M: MaterialDef + 'static>(&self) -> Result<(), AtlasError> {
+        for definition in T::DEFINITIONS {
+            if let BlockRenderer::Model(name) = definition.renderer {
+                if self.get(name).is_none() { return Err(AtlasError::MissingModel { model: name.to_string(), user: format!("Block {:?}", definition.material) }) }
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterates all models and their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &BlockModel)> { self.models.iter() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::define_blocks_materials;
+
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        AIR => { transparent: true, renderer: BlockRenderer::None },
+        POST => { transparent: true, renderer: BlockRenderer::Model("post") }
+    ]);
+
+    const POST: &str = r#"{ "elements": [{ "from": [6, 0, 6], "to": [10, 16, 10], "faces": { "above": { "tile": 1, "cullface": "above" }, "north": { "tile": 2 } } }] }"#;
+
+    #[test]
+    fn validate_finds_missing_models() {
+        let mut models = BlockModels::new();
+        assert!(matches!(models.validate::<Blocks, Material>(), Err(AtlasError::MissingModel { model, .. }) if model == "post"));
+        models.insert("post", BlockModel::from_json(POST).unwrap());
+        assert!(models.validate::<Blocks, Material>().is_ok());
+    }
+
+    #[test]
+    fn cullfaces_hide_faces() {
+        let model = BlockModel::from_json(POST).unwrap();
+        assert_eq!(model.render(|_| true).len(), 2);
+        let faces = model.render(|direction| direction != Direction::Above);
+        assert_eq!(faces.len(), 1);
+        assert_eq!(*faces[0].0, BlockModelTile::Index(2));
+        assert!(faces[0].1.iter().all(|vertex| vertex.position[2] == 10.0 / 16.0));
+    }
+}
@@ -1,9 +1,9 @@
 use std::fmt::Debug;
 
 use cgmath::*;
-use forte_engine::{primitives::vertices::Vertex, math::vec::VecExt};
+use forte_engine::{primitives::vertices::Vertex, math::{transforms::Transform, vec::VecExt}};
 
-use crate::terrain::lookup;
+use crate::terrain::{animations::TileAnimations, atlas::{BlockAtlas, Tile, TileNames}, block_entities::BlockEntityFactory, block_models::{face_uv, BlockModels}, connected::ConnectedMode, lookup, shapes::{self, BlockShape, SideCover, StairsCorner}, states::{BlockProperty, BlockState, Direction}, ticks::TickCallback, vertices::ChunkVertex};
 
 /// A macro to make creating block and material definitions easier, as a material definition is just a quick enum u16 represented enum to reference a block definition.
 /// See the documentation for `MaterialDef`, `BlockDef` and `BlockDefinitions` for more info.
@@ -21,18 +21,43 @@ use crate::terrain::lookup;
 ///         },
 ///         GRASS => {
 ///             transparent: false,
-///             renderer: BlockRenderer::Standard(0, 1, 0, 0, 0, 0)
+///             renderer: BlockRenderer::Standard(Tile::Index(0), Tile::Index(1), Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0)),
+///             tint: [Some(0), None, None, None, None, None], // Optionally, give the tint index of each face, above, below, north, south, east, west.  See the `TintProvider` documentation for more info.
+///             tick: grow_grass                // Optionally, give a `TickCallback` to update this block.  See the `TickScheduler` documentation for more info.
+///         },
+///         LOG => {
+///             transparent: false,
+///             renderer: BlockRenderer::Standard(Tile::Named("log_top"), Tile::Named("log_top"), Tile::Named("log"), Tile::Named("log"), Tile::Named("log"), Tile::Named("log")),
+///             properties: [BlockProperty::Axis] // Optionally, declare the `BlockProperty`s stored in this blocks data.  See the `BlockState` documentation for more info.
+///         },
+///         SIGN => {
+///             transparent: true,
+///             renderer: BlockRenderer::BlockEntity,
+///             entity: |_| Box::new(Sign::default()) // Optionally, give a `BlockEntityFactory` to create extra state for this block.  See the `BlockEntity` documentation for more info.
 ///         }
 ///     ]
 /// );
 /// ```
 #[macro_export]
 macro_rules! define_blocks_materials {
+    // optional block definition fields
+    (@option) => { None };
+    (@option $value:expr) => { Some($value) };
+    (@tint) => { [None; 6] };
+    (@tint $value:expr) => { $value };
+
     (
         $blocks_name:ident, 
         $mat_name:ident, 
         $atlas:expr, 
-        [$($variant:ident => { transparent: $transparent:expr, renderer: $renderer:expr }),*]
+        [$($variant:ident => { 
+            transparent: $transparent:expr, 
+            renderer: $renderer:expr
+            $(, properties: [$($property:expr),*])?
+            $(, tint: $tint:expr)?
+            $(, tick: $tick:expr)?
+            $(, entity: $entity:expr)?
+        }),*]
     ) => {
         // create material of all variants
         #[derive(Clone, Copy, Debug)]
@@ -53,7 +78,7 @@ macro_rules! define_blocks_materials {
         }
 
         // make the mateial a material definition
-        use forte_cubes::terrain::blocks::{MaterialDef, BlockDef, BlockDefinitions};
+        use $crate::terrain::blocks::{MaterialDef, BlockDef, BlockDefinitions};
         impl MaterialDef for $mat_name {}
 
         // generate block defintions
@@ -65,10 +90,20 @@ macro_rules! define_blocks_materials {
                 $(BlockDef {
                     material: $mat_name::$variant,
                     transparent: $transparent,
-                    renderer: $renderer
+                    renderer: $renderer,
+                    properties: &[$($($property),*)?],
+                    tint: $crate::define_blocks_materials!(@tint $($tint)?),
+                    tick: $crate::define_blocks_materials!(@option $($tick)?),
+                    entity: $crate::define_blocks_materials!(@option $($entity)?)
                 }),*
             ];
         }
+
+        // make sure the properties of every block fit in its data slot
+        $(const _: () = assert!(
+            $crate::terrain::states::BlockProperty::total_bits(&[$($($property),*)?]) <= u16::BITS,
+            concat!("The properties of ", stringify!($variant), " do not fit in the 16 bits of its data slot!")
+        );)*
     };
 }
 
@@ -78,7 +113,7 @@ pub trait MaterialDef: Clone + Copy + Debug + Into<u16> + From<u16> {}
 
 /// This trait is implemented by block definitions to be a continer for information needed for block definitions.
 pub trait BlockDefinitions<M: MaterialDef + 'static> {
-    /// The path to the texture atlas to be used to render all of the following `BlockDef`s
+    /// The path to the texture atlas to be used to render all of the following `BlockDef`s.  See `BlockAtlas` documentation for more info.
     const ATLAS: &'static str;
     /// An array of `BlockDef`s that are part of the `BlockDefinition` trait.  See `BlockDef` documentation for more info.
     const DEFINITIONS: &'static [BlockDef<M>];
@@ -91,7 +126,150 @@ pub struct BlockDef<M: MaterialDef + 'static> {
     /// Is this block transparent?
     pub transparent: bool,
     /// The `BlockRenderer` to be used to draw this block.  See `BlockRenderer` documentation for more info.
-    pub renderer: BlockRenderer<M>
+    pub renderer: BlockRenderer<M>,
+    /// The properties stored in the data slot of this block, in the order they are packed.  See `BlockState` documentation for more info.
+    pub properties: &'static [BlockProperty],
+    /// The tint index of each face of this block in the order above, below, north, south, east, west, or `None` if the face is not tinted.  Faces are matched by the direction they point before the block is rotated to its orientation, so the tinted face turns with the block.  See `TintProvider` documentation for more info.
+    pub tint: [Option<u8>; 6],
+    /// An optional callback that is called when this block is ticked by a `TickScheduler`.  See `TickScheduler` documentation for more info.
+    pub tick: Option<TickCallback<M>>,
+    /// An optional callback that creates the block entity of this block whenever it is placed in a chunk.  See `BlockEntity` documentation for more info.
+    pub entity: Option<BlockEntityFactory>
+}
+
+impl <M: MaterialDef + 'static> BlockDef<M> {
+    /// Colours the given vertices of this block by the tint of the face they point along, if the resources have a `TintProvider`.
+    ///
+    /// Arguments:
+    /// * vertices: &mut Vec<ChunkVertex> - The vertices to tint.
+    /// * rotation: Matrix3<f32> - The rotation the vertices were rendered with, see `BlockRenderer::rotation`.  This is undone to find the face each vertex was declared on.
+    /// * transform: &Transform - The transform of the mesh the vertices are in, used to find the world position of each vertex.
+    /// * resources: RenderResources - The resources that give the tint provider.
+    pub fn tint_vertices(&self, vertices: &mut Vec<ChunkVertex>, rotation: Matrix3<f32>, transform: &Transform, resources: RenderResources) {
+        let tints = match resources.tints { Some(tints) => tints, None => return };
+        let matrix = transform.to_mat();
+        vertices.for_each_mut(|vertex| {
+            // rotations are orthogonal, so the transpose turns the normal back to the face it was declared on
+            let direction = Direction::from_normal(rotation.transpose() * Vector3::from(vertex.normal));
+            if let Some(tint) = self.tint[direction.index()] {
+                vertex.color = tints.tint(tint, (matrix * Vector3::from(vertex.position).extend(1.0)).truncate());
+            }
+        });
+    }
+
+    /// Gets how much of the given side of its block space this block covers, which hides the faces of the neighbour on that side.  Opaque blocks cover their whole side, blocks drawn as a `BlockShape` cover what their shape fills, and other transparent blocks cover nothing.
+    ///
+    /// Arguments:
+    /// * data: u16 - The data of this block.
+    /// * side: Direction - The side of the block space.
+    pub fn side_cover(&self, data: u16, side: Direction) -> SideCover {
+        if !self.transparent { return SideCover::FULL }
+        match self.renderer.shape() {
+            Some((shape, _)) => shapes::side_cover(shape, BlockState::new(self.properties, data), side),
+            None => SideCover::default()
+        }
+    }
+}
+
+/// Provides the colour that tinted block faces are multiplied by, like grass or water changing colour between biomes.
+///
+/// This is implemented for any `Fn(u8, Vector3<f32>) -> [f32; 3]` which takes the tint index of the face and the world position of the vertex, and returns an RGB colour.
+pub trait TintProvider {
+    /// Gets the colour of a vertex with the given tint index at the given world position.
+    fn tint(&self, index: u8, position: Vector3<f32>) -> [f32; 3];
+}
+
+impl <F: Fn(u8, Vector3<f32>) -> [f32; 3]> TintProvider for F {
+    fn tint(&self, index: u8, position: Vector3<f32>) -> [f32; 3] { self(index, position) }
+}
+
+/// The shared resources used by `BlockRenderer`s while rendering blocks into a chunk mesh.
+#[derive(Clone, Copy)]
+pub struct RenderResources<'a> {
+    /// The names of the tiles in the atlas, used to resolve `Tile::Named`.
+    pub tiles: &'a TileNames,
+    /// The animated tiles of the atlas, used to resolve `Tile::Animated`.
+    pub animations: &'a TileAnimations,
+    /// The models used by `Model` renderers.
+    pub models: &'a BlockModels,
+    /// The provider of colours for tinted faces.  Tinted faces are not coloured if this is `None`.
+    pub tints: Option<&'a dyn TintProvider>
+}
+
+impl <'a> Debug for RenderResources<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderResources")
+            .field("tiles", self.tiles)
+            .field("animations", self.animations)
+            .field("models", self.models)
+            .field("tints", &self.tints.is_some())
+            .finish()
+    }
+}
+
+impl <'a> RenderResources<'a> {
+    /// Creates the resources to render blocks with the given atlas and models, without tints.
+    pub fn new(atlas: &'a BlockAtlas, models: &'a BlockModels) -> Self {
+        Self { tiles: &atlas.names, animations: &atlas.animations, models, tints: None }
+    }
+
+    /// Returns these resources with the given tint provider.
+    pub fn with_tints(self, tints: &'a dyn TintProvider) -> Self { Self { tints: Some(tints), ..self } }
+
+    /// Resolves the given tile to its atlas layer and animation.  The animation is 0 when not animated, or the index of the animation in `animations` plus 1.
+    /// Animations that are not in `animations` are drawn as their first frame.
+    pub fn resolve(&self, tile: &Tile) -> (u16, u32) {
+        let animation = match tile {
+            Tile::Animated(animation) => self.animations.index(animation).map_or(0, |index| index + 1),
+            _ => 0
+        };
+        (tile.resolve(self.tiles), animation)
+    }
+}
+
+/// The materials and data of the 3x3x3 blocks around a block being rendered, for renderers that depend on more than the 6 touching blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Neighbourhood {
+    blocks: [[[(u16, u16); 3]; 3]; 3]
+}
+
+impl Neighbourhood {
+    /// Creates a neighbourhood from the (material, data) of the blocks around a block, indexed by x, y and z offset plus 1.
+    pub fn new(blocks: [[[(u16, u16); 3]; 3]; 3]) -> Self { Self { blocks } }
+
+    /// Gets the (material, data) of the block at the given offset, where each component of the offset is from -1 to 1.
+    pub fn get(&self, offset: Vector3<i32>) -> (u16, u16) { self.blocks[(offset.x + 1) as usize][(offset.y + 1) as usize][(offset.z + 1) as usize] }
+
+    /// Gets the material of the block at the given offset, where each component of the offset is from -1 to 1.
+    pub fn material(&self, offset: Vector3<i32>) -> u16 { self.get(offset).0 }
+}
+
+/// The blocks around a block being rendered, given to `BlockRenderer::render` and `Custom` renderers.
+#[derive(Clone, Copy)]
+pub struct Neighbours<'a, M: MaterialDef + 'static> {
+    /// The definitions of the 6 touching blocks in the order above, below, north, south, east, west.
+    pub definitions: [&'a BlockDef<M>; 6],
+    /// The materials and data of the 3x3x3 blocks around the block.
+    pub neighbourhood: &'a Neighbourhood
+}
+
+impl <'a, M: MaterialDef + 'static> Neighbours<'a, M> {
+    /// Creates the neighbours of a block from the definitions of the 6 touching blocks in the order above, below, north, south, east, west and the blocks around it.
+    pub fn new(definitions: [&'a BlockDef<M>; 6], neighbourhood: &'a Neighbourhood) -> Self { Self { definitions, neighbourhood } }
+
+    /// Gets the definition of the block touching in the given direction.
+    pub fn get(&self, direction: Direction) -> &'a BlockDef<M> { self.definitions[direction.index()] }
+
+    /// Gets the state of the block touching in the given direction.
+    pub fn state(&self, direction: Direction) -> BlockState {
+        let (_, data) = self.neighbourhood.get(direction.normal().cast::<i32>().unwrap());
+        BlockState::new(self.get(direction).properties, data)
+    }
+
+    /// Is a face on the given side of the block, covering the given part of that side, hidden by the block touching that side?  Faces are hidden by opaque blocks and by shapes that cover them, see `BlockDef::side_cover`.
+    pub fn hides(&self, side: Direction, face: SideCover) -> bool {
+        self.get(side).side_cover(self.state(side).data, side.opposite()).hides(face)
+    }
 }
 
 /// The `BlockRenderer` enum defines how a block should be rendered.
@@ -100,67 +278,157 @@ pub struct BlockDef<M: MaterialDef + 'static> {
 pub enum BlockRenderer<M: MaterialDef + 'static> {
     /// Render nothing.
     None,
-    /// Just render a standard 1x1x1 block.  The u16's represent the atlas indices to texture each face of the cube.  They are in the order above, below, north, south, east, west.
-    Standard(u16, u16, u16, u16, u16, u16),
-    /// A csutom renderer that takes in a function that renders a `Vec<Vertex>` from 6 block definitions for each above, below, north, south, east, west.
-    Custom(
-        fn(
-            &BlockDef<M>, 
-            &BlockDef<M>, 
-            &BlockDef<M>, 
-            &BlockDef<M>, 
-            &BlockDef<M>, 
-            &BlockDef<M>
-        ) -> Vec<Vertex>
-    ),
+    /// Just render a standard 1x1x1 block.  The `Tile`s represent the atlas tiles to texture each face of the cube.  They are in the order above, below, north, south, east, west.
+    Standard(Tile, Tile, Tile, Tile, Tile, Tile),
+    /// Render a 1x1x1 block that is rotated by the "facing" or "axis" property of its `BlockState`.  The `Tile`s represent the atlas tiles to texture each face of the cube when facing north or aligned to the Y axis.  They are in the order above, below, north, south, east, west.
+    Oriented(Tile, Tile, Tile, Tile, Tile, Tile),
+    /// A csutom renderer that takes in a function that renders a `Vec<ChunkVertex>` from the state of the block and the blocks around it.  See `Neighbours` documentation for more info.
+    Custom(fn(BlockState, Neighbours<'_, M>) -> Vec<ChunkVertex>),
+    /// Render a 1x1x1 block whose faces connect to neighbouring blocks of the same material, like glass.  Faces touching the same material are not rendered.
+    /// The `Tile` is the first of the tile variants in the atlas, see `ConnectedMode` for the order of the variants.  The tile can not be animated, which `BlockAtlasBuilder::validate` checks.
+    Connected(Tile, ConnectedMode),
+    /// Render a slab in the bottom or top half of the block given by the "half" property.  The `Tile`s texture the faces pointing above, below and to the sides.
+    /// Shape renderers do not fill their whole block, so their blocks should be transparent.  Faces of neighbours are still hidden where the shape covers them, see `BlockShape`.
+    Slab(Tile, Tile, Tile),
+    /// Render stairs that step up towards the "facing" property, upside down when the "half" property is top, and bend into corners to join neighbouring stairs.  The `Tile`s texture the faces pointing above, below and to the sides.
+    Stairs(Tile, Tile, Tile),
+    /// Render a wedge that slopes up towards the "facing" property, upside down when the "half" property is top.  The `Tile`s texture the sloped face, the flat bottom and the sides.
+    Slope(Tile, Tile, Tile),
+    /// Render a post half a block wide along the "axis" property.  The `Tile`s texture the ends and the sides.
+    Pillar(Tile, Tile),
+    /// Render the `BlockModel` with the given name from the `BlockModels` given when meshing.  The model is rotated by the "facing" or "axis" property of the blocks `BlockState`, like `Oriented`.
+    Model(&'static str),
     /// Effectively renders nothing.  It just marks a `BlockDef` that this is a block entity that needs to be rendered later.
     BlockEntity
 }
 
 impl <M: MaterialDef + 'static> BlockRenderer<M> {
-    /// This function renders this block into a `Vec<Vertex>`
+    /// This function renders this block into a `Vec<ChunkVertex>`
     /// 
     /// Arguments:
     /// * &self - This block renderer
     /// * position: Vector3<f32> - The position of this block in the chunk.
-    /// * tex_size: Vector2<u32> - The size of the texture.
-    /// * above: &BlockDef<M> - The block above.
-    /// * below: &BlockDef<M> - The block below.
-    /// * north: &BlockDef<M> - The block north.
-    /// * south: &BlockDef<M> - The block south.
-    /// * east: &BlockDef<M> - The block east.
-    /// * west: &BlockDef<M> - The block west.
+    /// * state: BlockState - The state of this block.
+    /// * resources: RenderResources - The resources used to resolve tiles and models.  See `RenderResources` documentation for more info.
+    /// * neighbours: Neighbours<M> - The blocks around this block.
     /// 
-    /// Retuns a rendered `Vec<Vertex>` of all the vertices of this block.
-    pub fn render(
-        &self,
-        position: Vector3<f32>, 
-        tex_size: Vector2<u32>,
-        above: &BlockDef<M>, 
-        below: &BlockDef<M>, 
-        north: &BlockDef<M>, 
-        south: &BlockDef<M>, 
-        east: &BlockDef<M>, 
-        west: &BlockDef<M>
-    ) -> Vec<Vertex> {
+    /// Retuns a rendered `Vec<ChunkVertex>` of all the vertices of this block.
+    pub fn render(&self, position: Vector3<f32>, state: BlockState, resources: RenderResources, neighbours: Neighbours<M>) -> Vec<ChunkVertex> {
+        // a face on a side of the block is hidden by the neighbour on that side if it is opaque or its shape covers the face
+        let hidden = |side: Direction, face: SideCover| neighbours.hides(side, face);
+        let neighbourhood = neighbours.neighbourhood;
+
         // render a vector of vertices
         let mut vec = match self {
             // if none or block entity, rendering now is not necessary
             Self::None | Self::BlockEntity => Vec::new(),
 
             // do custom rendering
-            Self::Custom(callback) => 
-                callback(above, below, north, south, east, west),
+            Self::Custom(callback) => callback(state, neighbours),
 
             // do standard above, below, north, south, east, west rendedring
             Self::Standard(tex_above, tex_below, tex_north, tex_south, tex_east, tex_west) => {
                 let mut vec = Vec::new();
-                if above.transparent { Self::append_face(&mut vec, &lookup::CUBE_TOP, tex_size, tex_above) }
-                if below.transparent { Self::append_face(&mut vec, &lookup::CUBE_BOTTOM, tex_size, tex_below) }
-                if north.transparent { Self::append_face(&mut vec, &lookup::CUBE_NORTH, tex_size, tex_north) }
-                if south.transparent { Self::append_face(&mut vec, &lookup::CUBE_SOUTH, tex_size, tex_south) }
-                if east.transparent { Self::append_face(&mut vec, &lookup::CUBE_EAST, tex_size, tex_east) }
-                if west.transparent { Self::append_face(&mut vec, &lookup::CUBE_WEST, tex_size, tex_west) }
+                if !hidden(Direction::Above, SideCover::FULL) { Self::append_face(&mut vec, &lookup::CUBE_TOP, resources.resolve(tex_above)) }
+                if !hidden(Direction::Below, SideCover::FULL) { Self::append_face(&mut vec, &lookup::CUBE_BOTTOM, resources.resolve(tex_below)) }
+                if !hidden(Direction::North, SideCover::FULL) { Self::append_face(&mut vec, &lookup::CUBE_NORTH, resources.resolve(tex_north)) }
+                if !hidden(Direction::South, SideCover::FULL) { Self::append_face(&mut vec, &lookup::CUBE_SOUTH, resources.resolve(tex_south)) }
+                if !hidden(Direction::East, SideCover::FULL) { Self::append_face(&mut vec, &lookup::CUBE_EAST, resources.resolve(tex_east)) }
+                if !hidden(Direction::West, SideCover::FULL) { Self::append_face(&mut vec, &lookup::CUBE_WEST, resources.resolve(tex_west)) }
+                vec
+            }
+
+            // do standard rendering with faces rotated to the orientation of the block
+            Self::Oriented(tex_above, tex_below, tex_north, tex_south, tex_east, tex_west) => {
+                let rotation = state.rotation();
+                let faces: [(&[Vertex], &Tile, Direction); 6] = [
+                    (&lookup::CUBE_TOP, tex_above, Direction::Above),
+                    (&lookup::CUBE_BOTTOM, tex_below, Direction::Below),
+                    (&lookup::CUBE_NORTH, tex_north, Direction::North),
+                    (&lookup::CUBE_SOUTH, tex_south, Direction::South),
+                    (&lookup::CUBE_EAST, tex_east, Direction::East),
+                    (&lookup::CUBE_WEST, tex_west, Direction::West)
+                ];
+
+                let mut vec = Vec::new();
+                for (face, tex, direction) in faces {
+                    // cull against the neighbour in the direction this face ends up pointing
+                    if hidden(Direction::from_normal(rotation * direction.normal()), SideCover::FULL) { continue }
+
+                    let mut face_vec = Vec::new();
+                    Self::append_face(&mut face_vec, face, resources.resolve(tex));
+                    Self::rotate_vertices(&mut face_vec, rotation);
+                    vec.extend(face_vec);
+                }
+                vec
+            }
+
+            // render faces that are not touching the same material with the variant matching their connected neighbours
+            Self::Connected(tile, mode) => {
+                let (layer, animation) = resources.resolve(tile);
+                let faces: [(&[Vertex], Direction); 6] = [
+                    (&lookup::CUBE_TOP, Direction::Above),
+                    (&lookup::CUBE_BOTTOM, Direction::Below),
+                    (&lookup::CUBE_NORTH, Direction::North),
+                    (&lookup::CUBE_SOUTH, Direction::South),
+                    (&lookup::CUBE_EAST, Direction::East),
+                    (&lookup::CUBE_WEST, Direction::West)
+                ];
+
+                let mut vec = Vec::new();
+                let material = neighbourhood.material(Vector3::zero());
+                for (face, direction) in faces {
+                    let (neighbour, _) = neighbourhood.get(direction.normal().cast::<i32>().unwrap());
+                    if neighbour == material || hidden(direction, SideCover::FULL) { continue }
+
+                    // generate texture coordinates that match the face axes used to find connections
+                    let mut face_vec = Vec::new();
+                    Self::append_face(&mut face_vec, face, (layer + mode.variant(direction, neighbourhood), animation));
+                    face_vec.for_each_mut(|vertex| {
+                        let (u, v) = face_uv(direction, Vector3::from(vertex.position));
+                        vertex.tex_coords = [u, v];
+                    });
+                    vec.extend(face_vec);
+                }
+                vec
+            }
+
+            // render the faces of a built in shape that are not hidden by the neighbour on their side
+            Self::Slab(..) | Self::Stairs(..) | Self::Slope(..) | Self::Pillar(..) => {
+                let (shape, tiles) = self.shape().expect("Shape renderer has no shape!");
+
+                // stairs bend to join the stairs next to them
+                let corner = if shape == BlockShape::Stairs {
+                    StairsCorner::find(state, |direction| {
+                        if !matches!(neighbours.get(direction).renderer, Self::Stairs(..)) { return None }
+                        Some(neighbours.state(direction))
+                    })
+                } else { StairsCorner::Straight };
+
+                let mut vec = Vec::new();
+                for face in shapes::shape_faces(shape, state, corner) {
+                    if face.side.is_some_and(|side| hidden(side, face.cover())) { continue }
+                    Self::append_face(&mut vec, &face.triangles(), resources.resolve(&tiles[face.tile]));
+                }
+                vec
+            }
+
+            // render a block model rotated to the orientation of the block
+            Self::Model(name) => {
+                // model names are checked when loading by `BlockModels::validate`, so a missing model draws nothing
+                let model = match resources.models.get(name) { Some(model) => model, None => return Vec::new() };
+                let rotation = state.rotation();
+
+                // cullfaces are given in model space, so rotate them into the world before checking the neighbour
+                let faces = model.render(|direction| !hidden(Direction::from_normal(rotation * direction.normal()), SideCover::FULL));
+
+                let mut vec = Vec::new();
+                for (tex, face) in faces {
+                    let mut face_vec = Vec::new();
+                    Self::append_face(&mut face_vec, &face, (tex.resolve(resources.tiles), 0));
+                    Self::rotate_vertices(&mut face_vec, rotation);
+                    vec.extend(face_vec);
+                }
                 vec
             }
         };
@@ -173,26 +441,132 @@ impl <M: MaterialDef + 'static> BlockRenderer<M> {
         });
 
         // return vertices
-        return vec;
-    }
-
-    fn append_face(target: &mut Vec<Vertex>, input: &[Vertex], tex_size: Vector2<u32>, idx: &u16) {
-        // create input vertices
-        let mut input = input.to_vec();
-
-        // reposition texture coords
-        let width = 16.0 / tex_size.x as f32;
-        let height = 16.0 / tex_size.y as f32;
-        let horizontal_position = *idx as f32 * width;
-        let layer = horizontal_position.floor();
-        let x = horizontal_position - layer;
-        let y = height as f32 * layer;
-        input.for_each_mut(|vertex| {
-            vertex.tex_coords[0] = vertex.tex_coords[0] * width + x;
-            vertex.tex_coords[1] = vertex.tex_coords[1] * height + y;
+        vec
+    }
+
+    /// Returns all `Tile`s this renderer uses.
+    pub fn tiles(&self) -> Vec<Tile> {
+        match self {
+            Self::Standard(a, b, c, d, e, f) | Self::Oriented(a, b, c, d, e, f) => vec![*a, *b, *c, *d, *e, *f],
+            Self::Connected(tile, _) => vec![*tile],
+            Self::Slab(a, b, c) | Self::Stairs(a, b, c) | Self::Slope(a, b, c) => vec![*a, *b, *c],
+            Self::Pillar(a, b) => vec![*a, *b],
+            _ => Vec::new()
+        }
+    }
+
+    /// Returns the tiles used to draw this block as a plain cube in chunks meshed at a lower `ChunkLod`, in the order above, below, north, south, east, west.  Shapes use their side tile on all four sides.
+    /// Returns `None` if this block is left out of lower levels of detail.
+    pub fn lod_tiles(&self) -> Option<[Tile; 6]> {
+        match self {
+            Self::Standard(a, b, c, d, e, f) | Self::Oriented(a, b, c, d, e, f) => Some([*a, *b, *c, *d, *e, *f]),
+            Self::Connected(tile, _) => Some([*tile; 6]),
+            _ => self.shape().map(|(_, [above, below, side])| [above, below, side, side, side, side])
+        }
+    }
+
+    /// Gets the rotation this renderer applies to the faces of a block with the given state.  `Oriented`, `Model`, `Slope` and `Pillar` blocks are turned to their orientation, other renderers draw their faces where they point in the world.
+    ///
+    /// Arguments:
+    /// * state: BlockState - The state of the block.
+    pub fn rotation(&self, state: BlockState) -> Matrix3<f32> {
+        match self {
+            Self::Oriented(..) | Self::Model(_) | Self::Slope(..) | Self::Pillar(..) => state.rotation(),
+            _ => Matrix3::identity()
+        }
+    }
+
+    /// Returns the built in shape this renderer draws and its tiles in the order above, below, side, or `None` if it does not draw one.  The ends of pillars use the above and below tiles.
+    pub fn shape(&self) -> Option<(BlockShape, [Tile; 3])> {
+        match self {
+            Self::Slab(a, b, c) => Some((BlockShape::Slab, [*a, *b, *c])),
+            Self::Stairs(a, b, c) => Some((BlockShape::Stairs, [*a, *b, *c])),
+            Self::Slope(a, b, c) => Some((BlockShape::Slope, [*a, *b, *c])),
+            Self::Pillar(a, b) => Some((BlockShape::Pillar, [*a, *a, *b])),
+            _ => None
+        }
+    }
+
+    fn append_face(target: &mut Vec<ChunkVertex>, input: &[Vertex], (layer, animation): (u16, u32)) {
+        // add vertices sampling the given atlas layer and animation
+        target.extend(input.iter().map(|vertex| ChunkVertex { animation, ..ChunkVertex::from_vertex(vertex, layer as u32) }));
+    }
+
+    /// Rotates the given vertices and their normals around the center of a 1x1x1 block.
+    pub fn rotate_vertices(vertices: &mut Vec<ChunkVertex>, rotation: Matrix3<f32>) {
+        let center = Vector3 { x: 0.5, y: 0.5, z: 0.5 };
+        vertices.for_each_mut(|vertex| {
+            let position = rotation * (Vector3::from(vertex.position) - center) + center;
+            let normal = rotation * Vector3::from(vertex.normal);
+            // round away floating point error so faces of neighbouring blocks line up exactly
+            vertex.position = [snap(position.x), snap(position.y), snap(position.z)];
+            vertex.normal = [snap(normal.x), snap(normal.y), snap(normal.z)];
         });
+    }
+}
+
+pub(crate) fn snap(value: f32) -> f32 { (value * 4096.0).round() / 4096.0 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::states::{Half, PropertyValue};
 
-        // add vertices
-        target.extend(input);
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        AIR => { transparent: true, renderer: BlockRenderer::None },
+        LOG => { transparent: false, renderer: BlockRenderer::Oriented(Tile::Index(0), Tile::Index(0), Tile::Index(1), Tile::Index(0), Tile::Index(0), Tile::Index(0)), properties: [BlockProperty::HorizontalFacing], tint: [None, None, Some(3), None, None, None] },
+        STAIRS => { transparent: true, renderer: BlockRenderer::Stairs(Tile::Index(0), Tile::Index(1), Tile::Index(2)), properties: [BlockProperty::HorizontalFacing, BlockProperty::Half] },
+        PILLAR => { transparent: false, renderer: BlockRenderer::Pillar(Tile::Index(0), Tile::Index(1)), properties: [BlockProperty::Axis] }
+    ]);
+
+    // tints by the world position of the vertex, with the tint index in red
+    fn tint(index: u8, position: Vector3<f32>) -> [f32; 3] { [index as f32, position.x, position.y] }
+
+    #[test]
+    fn rotated_blocks_tint_the_declared_face() {
+        let (tiles, animations, models) = (TileNames::new(), TileAnimations::new(), BlockModels::new());
+        let resources = RenderResources { tiles: &tiles, animations: &animations, models: &models, tints: Some(&tint) };
+        let definition = &Blocks::DEFINITIONS[Material::LOG as usize];
+        let state = BlockState::default(definition.properties).with("facing", PropertyValue::Direction(Direction::East));
+
+        let air = &Blocks::DEFINITIONS[Material::AIR as usize];
+        let neighbourhood = Neighbourhood::new([[[(0, 0); 3]; 3]; 3]);
+        let mut vertices = definition.renderer.render(Vector3::zero(), state, resources, Neighbours::new([air; 6], &neighbourhood));
+        let transform = Transform { position: Vector3::new(10.0, 0.0, 0.0), scale: Vector3::from_value(2.0), ..Default::default() };
+        definition.tint_vertices(&mut vertices, definition.renderer.rotation(state), &transform, resources);
+
+        // the north face now points east and is the only tinted face, at the scaled and moved position
+        assert_eq!(vertices.len(), 36);
+        for vertex in vertices {
+            if Direction::from_normal(Vector3::from(vertex.normal)) == Direction::East {
+                assert_eq!(vertex.color[0], 3.0);
+                assert_eq!(vertex.color[1], 10.0 + vertex.position[0] * 2.0);
+                assert_eq!(vertex.color[2], vertex.position[1] * 2.0);
+            } else { assert_eq!(vertex.color, [1.0, 1.0, 1.0]); }
+        }
+    }
+
+    #[test]
+    fn shapes_use_their_side_tile_at_lower_detail() {
+        let (a, b, c) = (Tile::Index(0), Tile::Index(1), Tile::Index(2));
+        assert_eq!(Blocks::DEFINITIONS[Material::STAIRS as usize].renderer.lod_tiles(), Some([a, b, c, c, c, c]));
+        assert_eq!(Blocks::DEFINITIONS[Material::PILLAR as usize].renderer.lod_tiles(), Some([a, a, b, b, b, b]));
+        assert_eq!(Blocks::DEFINITIONS[Material::AIR as usize].renderer.lod_tiles(), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn stairs_cover_what_every_corner_fills() {
+        let definition = &Blocks::DEFINITIONS[Material::STAIRS as usize];
+        let state = BlockState::default(definition.properties).with("half", PropertyValue::Half(Half::Bottom));
+
+        // the bottom is always full, but no part of the top is filled by both outer corners, and asking again gives the same answer
+        for _ in 0 .. 2 {
+            assert_eq!(definition.side_cover(state.data, Direction::Below), SideCover::FULL);
+            assert_eq!(definition.side_cover(state.data, Direction::Above).cells, 0);
+        }
+
+        let top = state.with("half", PropertyValue::Half(Half::Top));
+        assert_eq!(definition.side_cover(top.data, Direction::Above), SideCover::FULL);
+        assert_eq!(definition.side_cover(top.data, Direction::Below).cells, 0);
+    }
+}
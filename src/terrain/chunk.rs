@@ -1,51 +1,198 @@
-use std::marker::PhantomData;
+use std::{collections::HashMap, marker::PhantomData};
 
 use cgmath::*;
-use forte_engine::{math::transforms::Transform, primitives::{mesh::Mesh, textures::Texture, transforms::TransformRaw, vertices::Vertex}, render::render_engine::RenderEngine, utils::resources::Handle};
+use forte_engine::{math::transforms::Transform, primitives::transforms::TransformRaw, render::render_engine::RenderEngine};
 use wgpu::util::DeviceExt;
 
-use crate::terrain::blocks::*;
+use crate::{culling::{Aabb, Frustum}, terrain::{atlas::BlockAtlas, block_entities::{BlockEntities, BlockEntity, SavedBlockEntity}, blocks::*, lod::{self, ChunkLod}, occlusion::ChunkVisibility, smooth::{self, ChunkMesher}, states::{BlockState, Direction}, vertices::ChunkVertex}};
 
-const CHUNK_SIZE: usize = 16;
+/// The default width, height and depth of a chunk in blocks.
+pub const CHUNK_SIZE: usize = 16;
 
 // todo general block renderer that can be easily repurosed for non-standard rendering, like a fench post
-// todo allow for tile entities (may have to wait)
 
+/// A trait for anything that stores blocks with local positions, so that block callbacks can edit blocks without knowing the concrete `BlockDefinitions` type.
+pub trait BlockStorage<M: MaterialDef + 'static> {
+    /// The size of this storage in blocks.
+    fn size(&self) -> Vector3<usize>;
+    /// Gets the material and data of the block at the given location.
+    fn get_block(&self, location: Vector3<usize>) -> (M, u16);
+    /// Sets the material and data of the block at the given location.
+    fn set_block(&mut self, location: Vector3<usize>, material: M, data: u16);
+}
+
+/// A box of blocks that is meshed and drawn as one.  The const generics `X`, `Y` and `Z` give the size of the chunk in blocks, which default to `CHUNK_SIZE`.
+///
+/// Larger chunks mean fewer draw calls but more work to remesh when a block changes, so tall columns like `Chunk<T, M, 32, 256, 32>` suit mostly static worlds while small chunks suit worlds that change often.
 #[derive(Debug)]
-pub struct Chunk<T: BlockDefinitions<M>, M: MaterialDef + 'static> {
+pub struct Chunk<T: BlockDefinitions<M>, M: MaterialDef + 'static, const X: usize = CHUNK_SIZE, const Y: usize = CHUNK_SIZE, const Z: usize = CHUNK_SIZE> {
     pub id: u32,
     pub transform: Transform,
-    data: [[[(u16, u16); CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE],
+    data: Vec<(u16, u16)>,
     buffer: Option<wgpu::Buffer>,
-    handle: Option<Handle<Mesh>>,
+    mesh: Option<wgpu::Buffer>,
+    vertex_count: u32,
+    dirty: bool,
+    lod: ChunkLod,
+    seams: [bool; 6],
+    visibility: ChunkVisibility,
+    entities: BlockEntities,
+    entity_blocks: Vec<Vector3<usize>>,
+    mesher: ChunkMesher,
+    border: HashMap<Vector3<i32>, (u16, u16)>,
     phantom_definitions: PhantomData<T>,
     phantom_material: PhantomData<M>
 }
 
-impl <T: BlockDefinitions<M>, M: MaterialDef + 'static> Chunk<T, M> {
-    pub fn empty(id: u32) -> Self { Self::new(id, [[[(0, 0); CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]) }
-    pub fn set(&mut self, location: Vector3<usize>, value: M, data: u16) { self.data[location.x][location.y][location.z] = (value.into(), data); }
-    pub fn get(&self, location: Vector3<usize>) -> M { self.data[location.x][location.y][location.z].0.into() }
-    pub fn handle(&self) -> Option<&Handle<Mesh>> { self.handle.as_ref() }
+impl <T: BlockDefinitions<M>, M: MaterialDef + 'static, const X: usize, const Y: usize, const Z: usize> Chunk<T, M, X, Y, Z> {
+    /// The size of this chunk type in blocks.
+    pub const SIZE: Vector3<usize> = Vector3 { x: X, y: Y, z: Z };
+
+    pub fn empty(id: u32) -> Self { Self::from_data(id, vec![(0, 0); X * Y * Z]) }
+    pub fn set(&mut self, location: Vector3<usize>, value: M, data: u16) {
+        let index = Self::index(location);
+        let previous = self.data[index].0;
+        self.data[index] = (value.into(), data);
+        self.dirty = true;
+
+        // replace the block entity when the material changes
+        if previous != self.data[index].0 {
+            self.entities.remove(location);
+            self.create_block_entity(location);
+        }
+    }
+
+    pub fn get(&self, location: Vector3<usize>) -> M { self.data[Self::index(location)].0.into() }
+    pub fn get_data(&self, location: Vector3<usize>) -> u16 { self.data[Self::index(location)].1 }
+    pub fn get_state(&self, location: Vector3<usize>) -> BlockState { BlockState::new(T::DEFINITIONS[self.get(location).into() as usize].properties, self.get_data(location)) }
+    pub fn set_state(&mut self, location: Vector3<usize>, value: M, state: BlockState) { self.set(location, value, state.data); }
+    pub fn size(&self) -> Vector3<usize> { Self::SIZE }
+    pub fn is_dirty(&self) -> bool { self.dirty }
+    pub fn mesh(&self) -> Option<&wgpu::Buffer> { self.mesh.as_ref() }
+    pub fn vertex_count(&self) -> u32 { self.vertex_count }
     pub fn buffer(&self) -> Option<&wgpu::Buffer> { self.buffer.as_ref() }
+    pub fn lod(&self) -> ChunkLod { self.lod }
+    pub fn block_entities(&self) -> &BlockEntities { &self.entities }
+    pub fn block_entities_mut(&mut self) -> &mut BlockEntities { &mut self.entities }
+
+    /// Gets the block entity at the given location if it has the type `E`.
+    pub fn block_entity<E: BlockEntity + 'static>(&self, location: Vector3<usize>) -> Option<&E> { self.entities.get(location) }
+
+    /// Gets the block entity at the given location mutably if it has the type `E`.
+    pub fn block_entity_mut<E: BlockEntity + 'static>(&mut self, location: Vector3<usize>) -> Option<&mut E> { self.entities.get_mut(location) }
+
+    /// The locations of the blocks in this chunk with a `BlockRenderer::BlockEntity` renderer, as of when it was last meshed.  These are drawn by `BlockEntityModels`.
+    pub fn block_entity_blocks(&self) -> &[Vector3<usize>] { &self.entity_blocks }
+
+    /// Saves the block entities of this chunk.  See `BlockEntities::save` for more info.
+    pub fn save_block_entities(&self) -> Vec<SavedBlockEntity> { self.entities.save() }
+
+    /// Restores block entities saved with `save_block_entities` into the block entities created for the blocks of this chunk.  Call this after the blocks are set.
+    pub fn load_block_entities(&mut self, saved: Vec<SavedBlockEntity>) { self.entities.load(saved) }
+
+    /// Which sides of this chunk can see each other through its transparent blocks, as of when it was last meshed.  See `visible_chunks` for how this is used.
+    pub fn visibility(&self) -> ChunkVisibility { self.visibility }
+
+    /// The center of this chunk in the world, ignoring its rotation and scale.  Use this with `LodDistances` to pick a level of detail.
+    pub fn center(&self) -> Vector3<f32> { self.transform.position + Self::SIZE.cast::<f32>().unwrap() / 2.0 }
+
+    /// The bounding box of this chunk in the world, from its transform.
+    pub fn bounds(&self) -> Aabb { Aabb::new(Vector3::zero(), Self::SIZE.cast::<f32>().unwrap()).transform(&self.transform.to_mat()) }
+
+    /// Returns true if this chunk may be seen inside the given frustum, so it should be drawn.
+    pub fn is_visible(&self, frustum: &Frustum) -> bool { frustum.intersects_aabb(&self.bounds()) }
 
-    pub fn new(id: u32, data: [[[(u16, u16); CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]) -> Self { 
-        Self { 
+    /// Sets the level of detail this chunk is meshed at, marking it dirty if it changed.
+    /// Sides of this chunk whose neighbour is at a different level of detail are closed off when meshing, so no gaps show between the two meshes.
+    ///
+    /// Arguments:
+    /// * lod: ChunkLod - The new level of detail.
+    /// * neighbours: [ChunkLod; 6] - The levels of detail of the neighbouring chunks in the order above, below, north, south, east, west.  Give `lod` for missing neighbours.
+    pub fn set_lod(&mut self, lod: ChunkLod, neighbours: [ChunkLod; 6]) {
+        let seams = neighbours.map(|neighbour| neighbour != lod);
+        if self.lod != lod || self.seams != seams { self.dirty = true; }
+        self.lod = lod;
+        self.seams = seams;
+    }
+
+    /// How this chunk is meshed.
+    pub fn mesher(&self) -> ChunkMesher { self.mesher }
+
+    /// Sets how this chunk is meshed, marking it dirty if it changed.
+    pub fn set_mesher(&mut self, mesher: ChunkMesher) {
+        if self.mesher != mesher { self.dirty = true; }
+        self.mesher = mesher;
+    }
+
+    /// Sets the blocks of the neighbouring chunks in the one block thick shell around this chunk, marking it dirty if they changed.  Smooth chunks use these so their surface joins the surface of their neighbours, see `ChunkMesher::Smooth`.
+    /// Blocks with no neighbour are treated like the closest block in this chunk, so the surface carries on to the edge of the chunk.
+    ///
+    /// Arguments:
+    /// * block: impl Fn(Vector3<i32>) -> Option<(u16, u16)> - Gets the material and data of the block at the given location relative to this chunk, or `None` if it is not loaded.
+    pub fn set_border(&mut self, block: impl Fn(Vector3<i32>) -> Option<(u16, u16)>) {
+        let mut border = HashMap::new();
+        for x in -1 ..= X as i32 {
+            for y in -1 ..= Y as i32 {
+                for z in -1 ..= Z as i32 {
+                    let location = Vector3 { x, y, z };
+                    if Self::contains(location) { continue }
+                    if let Some(value) = block(location) { border.insert(location, value); }
+                }
+            }
+        }
+        if self.border != border { self.dirty = true; }
+        self.border = border;
+    }
+
+    /// Creates a chunk from the (material, data) of its blocks, indexed by `data[x][y][z]`.
+    pub fn new(id: u32, data: [[[(u16, u16); Z]; Y]; X]) -> Self { 
+        Self::from_data(id, data.iter().flatten().flatten().copied().collect())
+    }
+
+    // Creates a chunk from the (material, data) of its blocks stored in the order of `index`.
+    fn from_data(id: u32, data: Vec<(u16, u16)>) -> Self {
+        assert_eq!(data.len(), X * Y * Z, "Chunk data must have one entry for every block!");
+        let mut chunk = Self { 
             id, 
             transform: Transform::default(), 
             data, 
             buffer: None,
-            handle: None,
-            phantom_definitions: PhantomData::default(),
-            phantom_material: PhantomData::default()
-        } 
+            mesh: None,
+            vertex_count: 0,
+            dirty: true,
+            lod: ChunkLod::Full,
+            seams: [false; 6],
+            visibility: ChunkVisibility::all(),
+            entities: BlockEntities::new(),
+            entity_blocks: Vec::new(),
+            mesher: ChunkMesher::Blocks,
+            border: HashMap::new(),
+            phantom_definitions: PhantomData,
+            phantom_material: PhantomData
+        };
+
+        // create the block entities of the given blocks
+        if T::DEFINITIONS.iter().any(|definition| definition.entity.is_some()) {
+            for x in 0 .. X {
+                for y in 0 .. Y {
+                    for z in 0 .. Z {
+                        chunk.create_block_entity(Vector3 { x, y, z });
+                    }
+                }
+            }
+        }
+        chunk
     }
 
-    pub fn draw<'rpass>(&'rpass mut self, engine: &'rpass RenderEngine, pass: &mut wgpu::RenderPass<'rpass>, atlas: &'rpass Handle<Texture>) {
+    /// Draws this chunks last generated mesh with the given atlas bound to bind group 1.  Chunks with an empty mesh draw nothing.
+    pub fn draw<'rpass>(&'rpass mut self, engine: &'rpass RenderEngine, pass: &mut wgpu::RenderPass<'rpass>, atlas: &'rpass BlockAtlas) {
         self.render_buffer(engine);
-        let handle = self.handle().expect("Chunk must have rendered mesh to be drawn!");
-        let buffer = self.buffer().expect("Buffer did not render!");
-        engine.draw_textured_list_mesh(pass, handle, &atlas, buffer, 1);
+        let mesh = match self.mesh.as_ref() { Some(mesh) => mesh, None => return };
+        let buffer = self.buffer.as_ref().expect("Buffer did not render!");
+        atlas.bind(pass, 1);
+        pass.set_vertex_buffer(0, mesh.slice(..));
+        pass.set_vertex_buffer(1, buffer.slice(..));
+        pass.draw(0 .. self.vertex_count, 0 .. 1);
     }
 
     pub(crate) fn render_buffer(&mut self, engine: &RenderEngine) {
@@ -67,56 +214,164 @@ impl <T: BlockDefinitions<M>, M: MaterialDef + 'static> Chunk<T, M> {
         }
     }
 
-    pub fn ensure_handle_buffer(&mut self, engine: &mut RenderEngine, chunk_engine: &Handle<Texture>) -> &Handle<Mesh> {
-        if self.handle.is_none() { self.gen_mesh(engine, chunk_engine); }
-        self.handle.as_ref().expect("Could not ensure handle in chunk!")
+    /// Regenerates this chunks mesh if any blocks changed since it was last generated.
+    pub fn ensure_mesh(&mut self, engine: &RenderEngine, resources: RenderResources) {
+        if self.dirty { self.gen_mesh(engine, resources); }
     }
 
-    pub fn gen_mesh(&mut self, engine: &mut RenderEngine, atlas: &Handle<Texture>) {
-        // create vertices and indices lists
-        let mut vertices: Vec<Vertex> = vec![];
+    pub fn gen_mesh(&mut self, engine: &RenderEngine, resources: RenderResources) {
+        // create vertices list
+        let mut vertices: Vec<ChunkVertex> = vec![];
 
-        // get atlas texture size
-        let texture = engine.texture(&atlas);
-        let tex_size = Vector2 { x: texture.texture.width(), y: texture.texture.height() };
+        // generate chunk, smooth chunks extract their surface, at full detail each block renders itself, otherwise render downsampled cells
+        if self.mesher == ChunkMesher::Smooth {
+            vertices = smooth::surface_nets(T::DEFINITIONS, Self::SIZE, |location| self.smooth_sample(location), &self.transform, resources);
+        } else if self.lod == ChunkLod::Full {
+            for x in 0 .. X {
+                for y in 0 .. Y {
+                    for z in 0 .. Z {
+                        self.gen_cube(Vector3 { x, y, z }, &mut vertices, resources);
+                    }
+                }
+            }
+        } else {
+            let scale = self.lod.scale();
+            let cells = lod::downsample(T::DEFINITIONS, Self::SIZE, scale, |location| self.data[Self::index(location)].0);
+            vertices = lod::mesh_cells(T::DEFINITIONS, &cells, Self::SIZE, scale, self.seams, &self.transform, resources);
+        }
 
-        // generate chunk
-        for x in 0 .. CHUNK_SIZE {
-            for y in 0 .. CHUNK_SIZE {
-                for z in 0 .. CHUNK_SIZE {
-                    self.gen_cube(Vector3 { x, y, z }, &mut vertices, tex_size);
+        // find the blocks that are drawn as block entities
+        self.entity_blocks.clear();
+        for x in 0 .. X {
+            for y in 0 .. Y {
+                for z in 0 .. Z {
+                    let location = Vector3 { x, y, z };
+                    if matches!(T::DEFINITIONS[self.data[Self::index(location)].0 as usize].renderer, BlockRenderer::BlockEntity) { self.entity_blocks.push(location); }
                 }
             }
         }
 
-        // create final mesh
-        let mesh = engine.create_mesh(format!("chunk_{}", self.id).as_str(), &vertices, &[]);
-        self.handle = Some(mesh);
+        // find which sides of the chunk can see each other for occlusion culling, smooth chunks can be seen through wherever they are outside of their surface
+        self.visibility = match self.mesher {
+            ChunkMesher::Smooth => ChunkVisibility::compute(Self::SIZE, |location| {
+                let (material, data) = self.data[Self::index(location)];
+                smooth::density(material, data) <= smooth::ISO_LEVEL
+            }),
+            ChunkMesher::Blocks => ChunkVisibility::compute(Self::SIZE, |location| T::DEFINITIONS[self.data[Self::index(location)].0 as usize].transparent)
+        };
+
+        // create final mesh, empty chunks do not get a buffer
+        self.vertex_count = vertices.len() as u32;
+        self.mesh = if vertices.is_empty() { None } else {
+            Some(engine.device.create_buffer_init(
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some(format!("chunk_{}", self.id).as_str()),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX
+                }
+            ))
+        };
+        self.dirty = false;
+    }
+
+    /// Generates the vertices of every block at full detail without creating a mesh, grouped by the material of the block they belong to in the order materials are first found.  Smooth chunks generate their surface, see `smooth::surface_nets_grouped`.  This is used to export chunks, see `ObjExport`.
+    pub fn gen_material_vertices(&self, resources: RenderResources) -> Vec<(M, Vec<ChunkVertex>)> {
+        if self.mesher == ChunkMesher::Smooth {
+            return smooth::surface_nets_grouped(T::DEFINITIONS, Self::SIZE, |location| self.smooth_sample(location), &self.transform, resources)
+                .into_iter()
+                .map(|(material, vertices)| (T::DEFINITIONS[material as usize].material, vertices))
+                .collect()
+        }
+
+        let mut groups: Vec<(M, Vec<ChunkVertex>)> = Vec::new();
+        for x in 0 .. X {
+            for y in 0 .. Y {
+                for z in 0 .. Z {
+                    let location = Vector3 { x, y, z };
+                    let mut vertices = Vec::new();
+                    self.gen_cube(location, &mut vertices, resources);
+                    if vertices.is_empty() { continue }
+
+                    let material = self.get(location);
+                    match groups.iter_mut().find(|(group, _)| (*group).into() == material.into()) {
+                        Some((_, group)) => group.extend(vertices),
+                        None => groups.push((material, vertices))
+                    }
+                }
+            }
+        }
+        groups
     }
 
-    fn gen_cube(&mut self, position: Vector3<usize>, vertices: &mut Vec<Vertex>, tex_size: Vector2<u32>) {
+    // Gets the block at the given location for smooth meshing, from the border outside of the chunk, or the nearest block of the chunk where there is no border.
+    fn smooth_sample(&self, location: Vector3<i32>) -> (u16, u16) {
+        if let Some(value) = self.border.get(&location) { return *value }
+        let size = Self::SIZE.cast::<i32>().unwrap();
+        let clamped = Vector3 { x: location.x.clamp(0, size.x - 1), y: location.y.clamp(0, size.y - 1), z: location.z.clamp(0, size.z - 1) };
+        self.data[Self::index(clamped.cast::<usize>().unwrap())]
+    }
+
+    fn gen_cube(&self, position: Vector3<usize>, vertices: &mut Vec<ChunkVertex>, resources: RenderResources) {
         // get current block
         let current = &T::DEFINITIONS[self.get(position).into() as usize];
+        let state = BlockState::new(current.properties, self.get_data(position));
         let combined = Vector3 { x: position.x as f32, y: position.y as f32, z: position.z as f32 };
 
-        // get relative block types and combined transform position and chunk position
-        let above = if position.y < CHUNK_SIZE - 1 { self.get(Vector3 { x: position.x, y: position.y + 1, z: position.z }) } else { 1.into() };
-        let below = if position.y > 0 { self.get(Vector3 { x: position.x, y: position.y - 1, z: position.z }) } else { 1.into() };
-        let north = if position.z < CHUNK_SIZE - 1 { self.get(Vector3 { x: position.x, y: position.y, z: position.z + 1 }) } else { 1.into() };
-        let south = if position.z > 0 { self.get(Vector3 { x: position.x, y: position.y, z: position.z - 1 }) } else { 1.into() };
-        let east = if position.x < CHUNK_SIZE - 1 { self.get(Vector3 { x: position.x + 1, y: position.y, z: position.z }) } else { 1.into() };
-        let west = if position.x > 0 { self.get(Vector3 { x: position.x - 1, y: position.y, z: position.z }) } else { 1.into() };
-
-        // get definitions for relatives
-        let above = &T::DEFINITIONS[above.into() as usize];
-        let below = &T::DEFINITIONS[below.into() as usize];
-        let north = &T::DEFINITIONS[north.into() as usize];
-        let south = &T::DEFINITIONS[south.into() as usize];
-        let east = &T::DEFINITIONS[east.into() as usize];
-        let west = &T::DEFINITIONS[west.into() as usize];
+        // get the 3x3x3 neighbourhood, blocks outside of the chunk are the first transparent material on seams and solid otherwise
+        let mut blocks = [[[(1, 0); 3]; 3]; 3];
+        for (x, plane) in blocks.iter_mut().enumerate() {
+            for (y, row) in plane.iter_mut().enumerate() {
+                for (z, block) in row.iter_mut().enumerate() {
+                    let location = Vector3 { x: position.x + x, y: position.y + y, z: position.z + z };
+                    if location.x == 0 || location.y == 0 || location.z == 0 || location.x > X || location.y > Y || location.z > Z {
+                        // only the blocks touching a side of the chunk can be on a seam
+                        let offset = location.cast::<i32>().unwrap() - position.cast::<i32>().unwrap() - Vector3::from_value(1);
+                        let side = Direction::ALL.into_iter().find(|direction| direction.normal().cast::<i32>().unwrap() == offset);
+                        if side.is_some_and(|side| self.seams[side.index()]) {
+                            let open = T::DEFINITIONS.iter().position(|definition| definition.transparent).unwrap_or(0);
+                            *block = (open as u16, 0);
+                        }
+                        continue
+                    }
+                    *block = self.data[Self::index(location - Vector3::from_value(1))];
+                }
+            }
+        }
+        let neighbourhood = Neighbourhood::new(blocks);
+
+        // get definitions for the touching blocks
+        let definitions = Direction::ALL.map(|direction| &T::DEFINITIONS[neighbourhood.material(direction.normal().cast::<i32>().unwrap()) as usize]);
 
         // render
-        let vec = current.renderer.render(combined, tex_size, above, below, north, south, east, west);
+        let mut vec = current.renderer.render(combined, state, resources, Neighbours::new(definitions, &neighbourhood));
+
+        // tint faces by the direction they were declared on
+        current.tint_vertices(&mut vec, current.renderer.rotation(state), &self.transform, resources);
         vertices.extend(vec);
     }
+
+    // Creates the block entity of the block at the given location, if its definition has one.
+    fn create_block_entity(&mut self, location: Vector3<usize>) {
+        let definition = &T::DEFINITIONS[self.get(location).into() as usize];
+        if let Some(factory) = definition.entity {
+            self.entities.insert(location, factory(self.get_state(location)));
+        }
+    }
+
+    // Returns true if the given location is inside this chunk.
+    fn contains(location: Vector3<i32>) -> bool {
+        location.x >= 0 && location.y >= 0 && location.z >= 0 && location.x < X as i32 && location.y < Y as i32 && location.z < Z as i32
+    }
+
+    // Gets the index of the block at the given location in the data of this chunk.
+    fn index(location: Vector3<usize>) -> usize {
+        assert!(location.x < X && location.y < Y && location.z < Z, "Location {:?} is outside of the chunk!", location);
+        (location.x * Y + location.y) * Z + location.z
+    }
+}
+
+impl <T: BlockDefinitions<M>, M: MaterialDef + 'static, const X: usize, const Y: usize, const Z: usize> BlockStorage<M> for Chunk<T, M, X, Y, Z> {
+    fn size(&self) -> Vector3<usize> { Chunk::size(self) }
+    fn get_block(&self, location: Vector3<usize>) -> (M, u16) { (self.get(location), self.get_data(location)) }
+    fn set_block(&mut self, location: Vector3<usize>, material: M, data: u16) { self.set(location, material, data) }
 }
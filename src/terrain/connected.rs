@@ -0,0 +1,138 @@
+use cgmath::*;
+
+use crate::terrain::{blocks::Neighbourhood, states::Direction};
+
+/// The tile layout used by a `BlockRenderer::Connected` block.  Every variant is a tile in the atlas at an offset from the base tile given to the renderer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectedMode {
+    /// 16 variants that only connect along edges.  The offset from the base tile is a bit mask of the connected edges where up is 1, right is 2, down is 4 and left is 8.
+    Simple,
+    /// 47 variants that connect along edges and corners.  The offset from the base tile is the index of the connected mask in `ConnectedMode::FULL_MASKS`.
+    Full
+}
+
+impl ConnectedMode {
+    /// The 47 masks of connected neighbours used by `Full`, in the order of their tiles.
+    /// Bits are up 1, up right 2, right 4, down right 8, down 16, down left 32, left 64 and up left 128.  Corners are only set when both of their edges are.
+    pub const FULL_MASKS: [u8; 47] = full_masks();
+
+    /// The number of tile variants this mode uses.
+    pub fn variants(&self) -> u16 {
+        match self { Self::Simple => 16, Self::Full => 47 }
+    }
+
+    /// Gets the offset from the base tile of the variant to use for a face.
+    ///
+    /// Arguments:
+    /// * direction: Direction - The direction the face is pointing.
+    /// * neighbourhood: &Neighbourhood - The blocks around the block being rendered.
+    pub fn variant(&self, direction: Direction, neighbourhood: &Neighbourhood) -> u16 {
+        let mask = connected_mask(direction, neighbourhood);
+        match self {
+            Self::Simple => ((mask & 1) | ((mask >> 1) & 2) | ((mask >> 2) & 4) | ((mask >> 3) & 8)) as u16,
+            Self::Full => Self::FULL_MASKS.iter().position(|full| *full == reduce(mask)).unwrap_or(0) as u16
+        }
+    }
+}
+
+/// The directions to the right and up of a face with the given direction, as seen from outside the block, matching the texture coordinates of block faces.
+pub(crate) fn face_axes(direction: Direction) -> (Vector3<i32>, Vector3<i32>) {
+    match direction {
+        Direction::Above => (Vector3::unit_x(), Vector3::unit_z()),
+        Direction::Below => (Vector3::unit_x(), -Vector3::unit_z()),
+        Direction::North => (-Vector3::unit_x(), Vector3::unit_y()),
+        Direction::South => (Vector3::unit_x(), Vector3::unit_y()),
+        Direction::East => (Vector3::unit_z(), Vector3::unit_y()),
+        Direction::West => (-Vector3::unit_z(), Vector3::unit_y())
+    }
+}
+
+// Gets the mask of neighbours in the plane of a face that are the same material as the block being rendered.
+fn connected_mask(direction: Direction, neighbourhood: &Neighbourhood) -> u8 {
+    let (right, up) = face_axes(direction);
+    let normal = direction.normal().cast::<i32>().unwrap();
+    let material = neighbourhood.material(Vector3::zero());
+    let offsets = [up, up + right, right, right - up, -up, -up - right, -right, up - right];
+
+    let mut mask = 0;
+    for (bit, offset) in offsets.iter().enumerate() {
+        // a neighbour only connects if it is the same material and its face in this direction is not covered by the same material
+        let connected = neighbourhood.material(*offset) == material && neighbourhood.material(*offset + normal) != material;
+        if connected { mask |= 1 << bit; }
+    }
+    mask
+}
+
+// Removes corners whose edges are not both connected.
+const fn reduce(mask: u8) -> u8 {
+    let mut result = mask & 0b0101_0101;
+    let mut corner = 0;
+    while corner < 4 {
+        let bit = corner * 2 + 1;
+        let before = (bit + 7) % 8;
+        let after = (bit + 1) % 8;
+        if mask & (1 << bit) != 0 && mask & (1 << before) != 0 && mask & (1 << after) != 0 { result |= 1 << bit; }
+        corner += 1;
+    }
+    result
+}
+
+const fn full_masks() -> [u8; 47] {
+    let mut result = [0; 47];
+    let mut count = 0;
+    let mut mask = 0;
+    while mask < 256 {
+        if reduce(mask as u8) == mask as u8 {
+            result[count] = mask as u8;
+            count += 1;
+        }
+        mask += 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // creates a neighbourhood of air with glass (material 1) at the given offsets and in the center
+    fn neighbourhood(glass: &[Vector3<i32>]) -> Neighbourhood {
+        let mut blocks = [[[(0, 0); 3]; 3]; 3];
+        blocks[1][1][1] = (1, 0);
+        for offset in glass { blocks[(offset.x + 1) as usize][(offset.y + 1) as usize][(offset.z + 1) as usize] = (1, 0); }
+        Neighbourhood::new(blocks)
+    }
+
+    #[test]
+    fn full_masks_are_unique_and_reduced() {
+        assert_eq!(ConnectedMode::FULL_MASKS[0], 0);
+        assert_eq!(ConnectedMode::FULL_MASKS[46], 255);
+        for (index, mask) in ConnectedMode::FULL_MASKS.iter().enumerate() {
+            assert_eq!(reduce(*mask), *mask);
+            assert!(!ConnectedMode::FULL_MASKS[.. index].contains(mask));
+        }
+    }
+
+    #[test]
+    fn edges_connect_in_the_plane_of_the_face() {
+        // glass to the east and north connects right and up on the top face
+        let blocks = neighbourhood(&[Vector3::unit_x(), Vector3::unit_z()]);
+        assert_eq!(connected_mask(Direction::Above, &blocks), 1 | 4);
+        assert_eq!(ConnectedMode::Simple.variant(Direction::Above, &blocks), 1 | 2);
+
+        // the corner only connects once both of its edges do
+        let blocks = neighbourhood(&[Vector3::unit_x(), Vector3::unit_z() + Vector3::unit_x()]);
+        assert_eq!(connected_mask(Direction::Above, &blocks), 2 | 4);
+        assert_eq!(reduce(connected_mask(Direction::Above, &blocks)), 4);
+        let blocks = neighbourhood(&[Vector3::unit_x(), Vector3::unit_z(), Vector3::unit_z() + Vector3::unit_x()]);
+        assert_eq!(ConnectedMode::Full.variant(Direction::Above, &blocks) as usize, ConnectedMode::FULL_MASKS.iter().position(|mask| *mask == 1 | 2 | 4).unwrap());
+    }
+
+    #[test]
+    fn covered_neighbours_do_not_connect() {
+        // glass to the east with glass on top of it has no visible top face to connect to
+        let blocks = neighbourhood(&[Vector3::unit_x(), Vector3::unit_x() + Vector3::unit_y()]);
+        assert_eq!(connected_mask(Direction::Above, &blocks), 0);
+        assert_eq!(connected_mask(Direction::South, &blocks), 2 | 4);
+    }
+}
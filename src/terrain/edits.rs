@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+
+use cgmath::*;
+
+use crate::terrain::{blocks::*, chunk::Chunk, streaming::{ChunkProvider, ChunkStreamer}};
+
+/// A trait for anything that stores blocks at world positions, like a single chunk or a `ChunkStreamer`, so that edits can span many chunks.
+pub trait BlockWorld<M: MaterialDef + 'static> {
+    /// Gets the material and data of the block at the given world position, or `None` if the block is not loaded.
+    fn block_at(&self, position: Vector3<i32>) -> Option<(M, u16)>;
+    /// Sets the material and data of the block at the given world position, marking its chunk dirty.  Returns false if the block is not loaded.
+    fn set_block_at(&mut self, position: Vector3<i32>, material: M, data: u16) -> bool;
+
+    /// Sets a block like `set_block_at`, but may leave follow up work, like updating the borders of smooth chunks, until `finish_edits` is called.  Bulk edits use this so that work is done once per chunk rather than once per block.  Defaults to `set_block_at`.
+    fn set_block_at_deferred(&mut self, position: Vector3<i32>, material: M, data: u16) -> bool { self.set_block_at(position, material, data) }
+    /// Does the work left by `set_block_at_deferred`.  Does nothing by default.
+    fn finish_edits(&mut self) {}
+
+    /// Saves the block entity at the given world position with `BlockEntity::save`, or returns `None` if there is no block entity or it saves null.  Worlds without block entities return `None` by default.
+    fn save_block_entity_at(&self, _position: Vector3<i32>) -> Option<serde_json::Value> { None }
+    /// Restores the block entity at the given world position with `BlockEntity::load`.  Returns false if there is no block entity there, which is the default.
+    fn load_block_entity_at(&mut self, _position: Vector3<i32>, _value: serde_json::Value) -> bool { false }
+}
+
+impl <T: BlockDefinitions<M>, M: MaterialDef + 'static, const X: usize, const Y: usize, const Z: usize> BlockWorld<M> for Chunk<T, M, X, Y, Z> {
+    fn block_at(&self, position: Vector3<i32>) -> Option<(M, u16)> {
+        let location = local_location(position, Self::SIZE)?;
+        Some((self.get(location), self.get_data(location)))
+    }
+
+    fn set_block_at(&mut self, position: Vector3<i32>, material: M, data: u16) -> bool {
+        match local_location(position, Self::SIZE) {
+            Some(location) => { self.set(location, material, data); true },
+            None => false
+        }
+    }
+
+    fn save_block_entity_at(&self, position: Vector3<i32>) -> Option<serde_json::Value> {
+        let location = local_location(position, Self::SIZE)?;
+        Some(self.block_entities().get_dyn(location)?.save()).filter(|value| !value.is_null())
+    }
+
+    fn load_block_entity_at(&mut self, position: Vector3<i32>, value: serde_json::Value) -> bool {
+        let entity = local_location(position, Self::SIZE).and_then(|location| self.block_entities_mut().get_dyn_mut(location));
+        match entity {
+            Some(entity) => { entity.load(value); true },
+            None => false
+        }
+    }
+}
+
+impl <T: BlockDefinitions<M>, M: MaterialDef + 'static, P: ChunkProvider<T, M, X, Y, Z>, const X: usize, const Y: usize, const Z: usize> BlockWorld<M> for ChunkStreamer<T, M, P, X, Y, Z> {
+    fn block_at(&self, position: Vector3<i32>) -> Option<(M, u16)> {
+        let (grid, location) = split_position(position, Chunk::<T, M, X, Y, Z>::SIZE);
+        let chunk = self.get(grid)?;
+        Some((chunk.get(location), chunk.get_data(location)))
+    }
+
+    fn set_block_at(&mut self, position: Vector3<i32>, material: M, data: u16) -> bool {
+        let set = self.set_block_at_deferred(position, material, data);
+        self.finish_edits();
+        set
+    }
+
+    fn set_block_at_deferred(&mut self, position: Vector3<i32>, material: M, data: u16) -> bool {
+        let (grid, location) = split_position(position, Chunk::<T, M, X, Y, Z>::SIZE);
+        match self.get_mut(grid) {
+            Some(chunk) => chunk.set(location, material, data),
+            None => return false
+        }
+
+        // blocks on the edge of a chunk are in the borders of its neighbours
+        let size = Chunk::<T, M, X, Y, Z>::SIZE;
+        if location.x == 0 || location.y == 0 || location.z == 0 || location.x == size.x - 1 || location.y == size.y - 1 || location.z == size.z - 1 { self.defer_borders(grid); }
+        true
+    }
+
+    fn finish_edits(&mut self) { self.refresh_pending_borders(); }
+
+    fn save_block_entity_at(&self, position: Vector3<i32>) -> Option<serde_json::Value> {
+        let (grid, location) = split_position(position, Chunk::<T, M, X, Y, Z>::SIZE);
+        Some(self.get(grid)?.block_entities().get_dyn(location)?.save()).filter(|value| !value.is_null())
+    }
+
+    fn load_block_entity_at(&mut self, position: Vector3<i32>, value: serde_json::Value) -> bool {
+        let (grid, location) = split_position(position, Chunk::<T, M, X, Y, Z>::SIZE);
+        match self.get_mut(grid).and_then(|chunk| chunk.block_entities_mut().get_dyn_mut(location)) {
+            Some(entity) => { entity.load(value); true },
+            None => false
+        }
+    }
+}
+
+// Converts a position to a location in a box of the given size, or `None` if it is outside of the box.
+fn local_location(position: Vector3<i32>, size: Vector3<usize>) -> Option<Vector3<usize>> {
+    let inside = position.x >= 0 && position.y >= 0 && position.z >= 0
+        && (position.x as usize) < size.x && (position.y as usize) < size.y && (position.z as usize) < size.z;
+    if inside { position.cast::<usize>() } else { None }
+}
+
+// Splits a world position into the grid position of its chunk and its location in that chunk.
+fn split_position(position: Vector3<i32>, size: Vector3<usize>) -> (Vector3<i32>, Vector3<usize>) {
+    let size = size.cast::<i32>().unwrap();
+    let grid = Vector3 { x: position.x.div_euclid(size.x), y: position.y.div_euclid(size.y), z: position.z.div_euclid(size.z) };
+    let location = Vector3 { x: position.x.rem_euclid(size.x), y: position.y.rem_euclid(size.y), z: position.z.rem_euclid(size.z) };
+    (grid, location.cast::<usize>().unwrap())
+}
+
+/// A single block changed by an `EditTransaction`.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockChange<M: MaterialDef + 'static> {
+    /// The world position of the block.
+    pub position: Vector3<i32>,
+    /// The material and data of the block before the transaction.
+    pub before: (M, u16),
+    /// The material and data of the block after the transaction.
+    pub after: (M, u16)
+}
+
+/// A group of block edits that are undone and redone together.
+///
+/// Edits are applied to the world straight away and the previous material and data of each block is recorded.  Block entities are recreated when their blocks are restored, so their contents are not undone.
+///
+/// Example
+/// ```rust,ignore
+/// let mut transaction = EditTransaction::new();
+/// transaction.set(&mut streamer, Vector3 { x: 0, y: 10, z: 0 }, Material::STONE, 0);
+/// transaction.set(&mut streamer, Vector3 { x: 1, y: 10, z: 0 }, Material::STONE, 0);
+/// history.commit(transaction);
+///
+/// // later, both blocks are restored in one go
+/// history.undo(&mut streamer);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EditTransaction<M: MaterialDef + 'static> {
+    changes: Vec<BlockChange<M>>,
+    indices: HashMap<Vector3<i32>, usize>
+}
+
+impl <M: MaterialDef + 'static> Default for EditTransaction<M> {
+    fn default() -> Self { Self::new() }
+}
+
+impl <M: MaterialDef + 'static> EditTransaction<M> {
+    /// Creates an empty transaction.
+    pub fn new() -> Self { Self { changes: Vec::new(), indices: HashMap::new() } }
+
+    pub fn changes(&self) -> &[BlockChange<M>] { &self.changes }
+    pub fn len(&self) -> usize { self.changes.len() }
+    pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+
+    /// Sets a block in the world and records the change in this transaction.  Blocks that already have the given material and data are left alone so their chunks are not marked dirty.
+    ///
+    /// Arguments:
+    /// * world: &mut impl BlockWorld<M> - The world to edit.
+    /// * position: Vector3<i32> - The world position of the block.
+    /// * material: M - The new material of the block.
+    /// * data: u16 - The new data of the block.
+    ///
+    /// Returns false if the block is not loaded.
+    pub fn set(&mut self, world: &mut impl BlockWorld<M>, position: Vector3<i32>, material: M, data: u16) -> bool {
+        let set = self.set_deferred(world, position, material, data);
+        world.finish_edits();
+        set
+    }
+
+    // Sets a block like `set` with `BlockWorld::set_block_at_deferred`, so bulk edits must call `BlockWorld::finish_edits` when done.
+    pub(crate) fn set_deferred(&mut self, world: &mut impl BlockWorld<M>, position: Vector3<i32>, material: M, data: u16) -> bool {
+        let before = match world.block_at(position) { Some(before) => before, None => return false };
+        if same(before, (material, data)) { return true }
+        world.set_block_at_deferred(position, material, data);
+
+        // keep the first before of blocks changed more than once
+        match self.indices.get(&position) {
+            Some(index) => self.changes[*index].after = (material, data),
+            None => {
+                self.indices.insert(position, self.changes.len());
+                self.changes.push(BlockChange { position, before, after: (material, data) });
+            }
+        }
+        true
+    }
+
+    /// Restores every block changed by this transaction to its state before the transaction.
+    pub fn undo(&self, world: &mut impl BlockWorld<M>) {
+        self.changes.iter().rev().for_each(|change| replay(world, change.position, change.before));
+        world.finish_edits();
+    }
+
+    /// Applies every block change of this transaction again after it was undone.
+    pub fn redo(&self, world: &mut impl BlockWorld<M>) {
+        self.changes.iter().for_each(|change| replay(world, change.position, change.after));
+        world.finish_edits();
+    }
+}
+
+// Sets a block only if it is different, so chunks that end up unchanged are not marked dirty.
+fn replay<M: MaterialDef + 'static>(world: &mut impl BlockWorld<M>, position: Vector3<i32>, (material, data): (M, u16)) {
+    if world.block_at(position).is_some_and(|current| !same(current, (material, data))) { world.set_block_at_deferred(position, material, data); }
+}
+
+fn same<M: MaterialDef + 'static>(a: (M, u16), b: (M, u16)) -> bool { a.0.into() == b.0.into() && a.1 == b.1 }
+
+/// A history of committed `EditTransaction`s that can be undone and redone in order.
+#[derive(Debug, Clone)]
+pub struct EditHistory<M: MaterialDef + 'static> {
+    undo: Vec<EditTransaction<M>>,
+    redo: Vec<EditTransaction<M>>,
+    /// The most transactions kept to undo.  The oldest transactions are forgotten first.
+    pub limit: usize
+}
+
+impl <M: MaterialDef + 'static> EditHistory<M> {
+    /// Creates an empty history that keeps up to the given number of transactions.
+    pub fn new(limit: usize) -> Self { Self { undo: Vec::new(), redo: Vec::new(), limit } }
+
+    pub fn can_undo(&self) -> bool { !self.undo.is_empty() }
+    pub fn can_redo(&self) -> bool { !self.redo.is_empty() }
+
+    /// Adds a transaction that was applied to the world, clearing any transactions that could be redone.  Empty transactions are ignored.
+    pub fn commit(&mut self, transaction: EditTransaction<M>) {
+        if transaction.is_empty() { return }
+        self.redo.clear();
+        self.undo.push(transaction);
+        if self.undo.len() > self.limit { self.undo.drain(0 .. self.undo.len() - self.limit); }
+    }
+
+    /// Undoes the last committed transaction.  Returns false if there was nothing to undo.
+    pub fn undo(&mut self, world: &mut impl BlockWorld<M>) -> bool {
+        let transaction = match self.undo.pop() { Some(transaction) => transaction, None => return false };
+        transaction.undo(world);
+        self.redo.push(transaction);
+        true
+    }
+
+    /// Redoes the last undone transaction.  Returns false if there was nothing to redo.
+    pub fn redo(&mut self, world: &mut impl BlockWorld<M>) -> bool {
+        let transaction = match self.redo.pop() { Some(transaction) => transaction, None => return false };
+        transaction.redo(world);
+        self.undo.push(transaction);
+        true
+    }
+
+    /// Forgets every transaction.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::define_blocks_materials;
+
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        AIR => { transparent: true, renderer: BlockRenderer::None },
+        STONE => { transparent: false, renderer: BlockRenderer::None },
+        DIRT => { transparent: false, renderer: BlockRenderer::None }
+    ]);
+
+    type World = Chunk<Blocks, Material, 4, 4, 4>;
+    const POSITION: Vector3<i32> = Vector3 { x: 1, y: 2, z: 3 };
+
+    fn material(world: &World) -> u16 { world.block_at(POSITION).unwrap().0.into() }
+
+    // sets the block at `POSITION` in its own transaction and commits it
+    fn edit(world: &mut World, history: &mut EditHistory<Material>, material: Material) {
+        let mut transaction = EditTransaction::new();
+        transaction.set(world, POSITION, material, 0);
+        history.commit(transaction);
+    }
+
+    #[test]
+    fn repeated_sets_keep_the_first_before() {
+        let mut world = World::empty(0);
+        let mut transaction = EditTransaction::new();
+        assert!(transaction.set(&mut world, POSITION, Material::STONE, 0));
+        assert!(transaction.set(&mut world, POSITION + Vector3::unit_x(), Material::STONE, 0));
+        assert!(transaction.set(&mut world, POSITION, Material::DIRT, 2));
+        assert!(!transaction.set(&mut world, Vector3::new(-1, 0, 0), Material::DIRT, 0));
+
+        assert_eq!(transaction.len(), 2);
+        let change = transaction.changes()[0];
+        assert_eq!((change.position, change.before.0.into(), change.after.0.into(), change.after.1), (POSITION, Material::AIR as u16, Material::DIRT as u16, 2));
+
+        transaction.undo(&mut world);
+        assert_eq!(material(&world), Material::AIR as u16);
+        transaction.redo(&mut world);
+        assert_eq!(world.block_at(POSITION).map(|(material, data)| (material.into(), data)), Some((Material::DIRT as u16, 2)));
+    }
+
+    #[test]
+    fn history_undoes_and_redoes_in_order() {
+        let mut world = World::empty(0);
+        let mut history = EditHistory::new(8);
+        edit(&mut world, &mut history, Material::STONE);
+        edit(&mut world, &mut history, Material::DIRT);
+
+        assert!(history.undo(&mut world));
+        assert_eq!(material(&world), Material::STONE as u16);
+        assert!(history.undo(&mut world));
+        assert_eq!(material(&world), Material::AIR as u16);
+        assert!(!history.undo(&mut world));
+
+        assert!(history.redo(&mut world));
+        assert_eq!(material(&world), Material::STONE as u16);
+
+        // committing after an undo forgets the transactions that could be redone
+        edit(&mut world, &mut history, Material::AIR);
+        assert!(!history.can_redo());
+        assert!(history.undo(&mut world));
+        assert_eq!(material(&world), Material::STONE as u16);
+    }
+
+    #[test]
+    fn history_forgets_the_oldest_transactions() {
+        let mut world = World::empty(0);
+        let mut history = EditHistory::new(1);
+        edit(&mut world, &mut history, Material::STONE);
+        edit(&mut world, &mut history, Material::DIRT);
+        assert!(history.undo(&mut world));
+        assert!(!history.undo(&mut world));
+        assert_eq!(material(&world), Material::STONE as u16);
+    }
+}
@@ -0,0 +1,286 @@
+use cgmath::*;
+use forte_engine::{math::{transforms::Transform, vec::VecExt}, primitives::vertices::Vertex};
+
+use crate::terrain::{blocks::*, lookup, states::Direction, vertices::ChunkVertex};
+
+/// The level of detail a chunk is meshed at.  Below `Full`, the chunk is split into cubic cells of `scale` blocks wide that are each drawn as a single cube of their dominant visible material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ChunkLod {
+    /// Every block is drawn with its own `BlockRenderer`.
+    #[default]
+    Full,
+    /// Cells of 2x2x2 blocks.
+    Half,
+    /// Cells of 4x4x4 blocks.
+    Quarter,
+    /// Cells of 8x8x8 blocks.
+    Eighth
+}
+
+impl ChunkLod {
+    /// All levels of detail from the most to the least detailed.
+    pub const ALL: [ChunkLod; 4] = [ChunkLod::Full, ChunkLod::Half, ChunkLod::Quarter, ChunkLod::Eighth];
+
+    /// The width of a cell at this level of detail in blocks.
+    pub fn scale(&self) -> usize {
+        match self { Self::Full => 1, Self::Half => 2, Self::Quarter => 4, Self::Eighth => 8 }
+    }
+}
+
+/// The distances from the camera at which chunks switch to lower levels of detail.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LodDistances {
+    /// The distance at which chunks switch to `ChunkLod::Half`.
+    pub half: f32,
+    /// The distance at which chunks switch to `ChunkLod::Quarter`.
+    pub quarter: f32,
+    /// The distance at which chunks switch to `ChunkLod::Eighth`.
+    pub eighth: f32
+}
+
+impl Default for LodDistances {
+    fn default() -> Self { Self { half: 64.0, quarter: 128.0, eighth: 256.0 } }
+}
+
+impl LodDistances {
+    /// Gets the level of detail for a chunk whose center is the given distance from the camera.
+    pub fn lod(&self, distance: f32) -> ChunkLod {
+        if distance >= self.eighth { ChunkLod::Eighth }
+        else if distance >= self.quarter { ChunkLod::Quarter }
+        else if distance >= self.half { ChunkLod::Half }
+        else { ChunkLod::Full }
+    }
+}
+
+/// Downsamples a box of blocks into cells of the given scale, returning the material of each cell or `None` if the cell is empty.  Cells are indexed by `(x * cells.y + y) * cells.z + z` where `cells` is `cell_count(size, scale)`.
+///
+/// Only blocks that have `BlockRenderer::lod_tiles` fill a cell, and a cell is only filled if at least half of its blocks do.
+/// The material of a filled cell is the most common material of its blocks that touch a transparent block, or the most common of all its blocks if none do.
+///
+/// Arguments:
+/// * definitions: &[BlockDef<M>] - The definitions of the materials in the blocks.
+/// * size: Vector3<usize> - The size of the box of blocks.
+/// * scale: usize - The width of a cell in blocks.
+/// * material: impl Fn(Vector3<usize>) -> u16 - Gets the material of the block at the given location.
+pub fn downsample<M: MaterialDef + 'static>(definitions: &[BlockDef<M>], size: Vector3<usize>, scale: usize, material: impl Fn(Vector3<usize>) -> u16) -> Vec<Option<u16>> {
+    let cells = cell_count(size, scale);
+    let is_transparent = |location: Vector3<usize>| definitions[material(location) as usize].transparent;
+
+    let mut result = Vec::with_capacity(cells.x * cells.y * cells.z);
+    for cx in 0 .. cells.x {
+        for cy in 0 .. cells.y {
+            for cz in 0 .. cells.z {
+                // count the materials in this cell, separating out those that can be seen
+                let mut all: Vec<(u16, usize)> = Vec::new();
+                let mut visible: Vec<(u16, usize)> = Vec::new();
+                let mut filled = 0;
+                let mut volume = 0;
+                for x in cx * scale .. ((cx + 1) * scale).min(size.x) {
+                    for y in cy * scale .. ((cy + 1) * scale).min(size.y) {
+                        for z in cz * scale .. ((cz + 1) * scale).min(size.z) {
+                            volume += 1;
+                            let location = Vector3 { x, y, z };
+                            let value = material(location);
+                            if definitions[value as usize].renderer.lod_tiles().is_none() { continue }
+                            filled += 1;
+                            count(&mut all, value);
+
+                            // blocks outside of the box are treated as solid, like when meshing at full detail
+                            let exposed = (x > 0 && is_transparent(Vector3 { x: x - 1, y, z }))
+                                || (x < size.x - 1 && is_transparent(Vector3 { x: x + 1, y, z }))
+                                || (y > 0 && is_transparent(Vector3 { x, y: y - 1, z }))
+                                || (y < size.y - 1 && is_transparent(Vector3 { x, y: y + 1, z }))
+                                || (z > 0 && is_transparent(Vector3 { x, y, z: z - 1 }))
+                                || (z < size.z - 1 && is_transparent(Vector3 { x, y, z: z + 1 }));
+                            if exposed { count(&mut visible, value); }
+                        }
+                    }
+                }
+
+                if filled * 2 < volume { result.push(None); continue }
+                let dominant = if visible.is_empty() { &all } else { &visible };
+                result.push(dominant.iter().max_by_key(|(_, count)| *count).map(|(value, _)| *value));
+            }
+        }
+    }
+    result
+}
+
+/// Meshes cells made by `downsample` into a cube for each filled cell, with a face on each side that touches an empty cell.  Tiles repeat once per block across each face.
+///
+/// Arguments:
+/// * definitions: &[BlockDef<M>] - The definitions of the materials in the cells.
+/// * cells: &[Option<u16>] - The cells to mesh.
+/// * size: Vector3<usize> - The size of the box of blocks the cells were downsampled from.
+/// * scale: usize - The width of a cell in blocks.
+/// * seams: [bool; 6] - Whether to close each side of the mesh in the order above, below, north, south, east, west.  Sides that are not closed treat cells outside of the mesh as solid.
+/// * transform: &Transform - The transform of the mesh, used for tints.
+/// * resources: RenderResources - The resources used to resolve tiles and tints.
+///
+/// Returns the vertices of the mesh, positioned in blocks.
+pub fn mesh_cells<M: MaterialDef + 'static>(
+    definitions: &[BlockDef<M>],
+    cells: &[Option<u16>],
+    size: Vector3<usize>,
+    scale: usize,
+    seams: [bool; 6],
+    transform: &Transform,
+    resources: RenderResources
+) -> Vec<ChunkVertex> {
+    let count = cell_count(size, scale);
+    let index = |x: usize, y: usize, z: usize| (x * count.y + y) * count.z + z;
+
+    let mut vertices = Vec::new();
+    for x in 0 .. count.x {
+        for y in 0 .. count.y {
+            for z in 0 .. count.z {
+                let definition = match cells[index(x, y, z)] { Some(value) => &definitions[value as usize], None => continue };
+                let tiles = definition.renderer.lod_tiles().expect("Downsampled cell has no level of detail tiles!");
+
+                // the cube is scaled up to the size of the cell, clipped to the edge of the box
+                let position = Vector3 { x: x * scale, y: y * scale, z: z * scale };
+                let width = Vector3 { x: scale.min(size.x - position.x) as f32, y: scale.min(size.y - position.y) as f32, z: scale.min(size.z - position.z) as f32 };
+
+                let mut vec = Vec::new();
+                for (side, direction) in Direction::ALL.iter().enumerate() {
+                    // find the neighbouring cell, which is empty outside of the mesh only on closed sides
+                    let normal = direction.normal().cast::<i32>().unwrap();
+                    let neighbour = Vector3 { x: x as i32 + normal.x, y: y as i32 + normal.y, z: z as i32 + normal.z };
+                    let outside = neighbour.x < 0 || neighbour.y < 0 || neighbour.z < 0
+                        || neighbour.x >= count.x as i32 || neighbour.y >= count.y as i32 || neighbour.z >= count.z as i32;
+                    let empty = if outside { seams[side] } else { cells[index(neighbour.x as usize, neighbour.y as usize, neighbour.z as usize)].is_none() };
+                    if !empty { continue }
+
+                    // repeat the tile once per block across the face, the atlas sampler repeats tiles
+                    let (layer, animation) = resources.resolve(&tiles[side]);
+                    let (u, v) = tile_axes(*direction);
+                    vec.extend(cube_face(*direction).iter().map(|vertex| {
+                        let mut vertex = ChunkVertex { animation, ..ChunkVertex::from_vertex(vertex, layer as u32) };
+                        vertex.tex_coords = [vertex.tex_coords[0] * width[u], vertex.tex_coords[1] * width[v]];
+                        vertex
+                    }));
+                }
+
+                // move the cube into place
+                vec.for_each_mut(|vertex| {
+                    vertex.position[0] = vertex.position[0] * width.x + position.x as f32;
+                    vertex.position[1] = vertex.position[1] * width.y + position.y as f32;
+                    vertex.position[2] = vertex.position[2] * width.z + position.z as f32;
+                });
+                definition.tint_vertices(&mut vec, Matrix3::identity(), transform, resources);
+                vertices.extend(vec);
+            }
+        }
+    }
+    vertices
+}
+
+/// Gets the number of cells along each axis when a box of blocks of the given size is split into cells of the given scale.  Cells at the far edges are cut short if the size is not a multiple of the scale.
+pub fn cell_count(size: Vector3<usize>, scale: usize) -> Vector3<usize> {
+    Vector3 { x: size.x.div_ceil(scale), y: size.y.div_ceil(scale), z: size.z.div_ceil(scale) }
+}
+
+// Gets the vertices of the face of a 1x1x1 cube pointing in the given direction.
+fn cube_face(direction: Direction) -> &'static [Vertex; 6] {
+    match direction {
+        Direction::Above => &lookup::CUBE_TOP,
+        Direction::Below => &lookup::CUBE_BOTTOM,
+        Direction::North => &lookup::CUBE_NORTH,
+        Direction::South => &lookup::CUBE_SOUTH,
+        Direction::East => &lookup::CUBE_EAST,
+        Direction::West => &lookup::CUBE_WEST
+    }
+}
+
+// Gets the axes that the u and v texture coordinates of the given face of `cube_face` run along.
+fn tile_axes(direction: Direction) -> (usize, usize) {
+    match direction {
+        Direction::Above | Direction::Below => (0, 2),
+        Direction::North | Direction::South => (0, 1),
+        Direction::East | Direction::West => (1, 2)
+    }
+}
+
+// Adds one to the count of the given value.
+fn count(counts: &mut Vec<(u16, usize)>, value: u16) {
+    match counts.iter_mut().find(|(other, _)| *other == value) {
+        Some((_, count)) => *count += 1,
+        None => counts.push((value, 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{define_blocks_materials, terrain::{animations::TileAnimations, atlas::{Tile, TileNames}, block_models::BlockModels, chunk::Chunk}};
+
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        STONE => { transparent: false, renderer: BlockRenderer::Standard(Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0)) },
+        DIRT => { transparent: false, renderer: BlockRenderer::Standard(Tile::Index(1), Tile::Index(1), Tile::Index(1), Tile::Index(1), Tile::Index(1), Tile::Index(1)) },
+        AIR => { transparent: true, renderer: BlockRenderer::None },
+        GLASS => { transparent: true, renderer: BlockRenderer::None }
+    ]);
+
+    const SIZE: Vector3<usize> = Vector3 { x: 4, y: 4, z: 4 };
+
+    #[test]
+    fn cells_take_their_most_common_visible_material() {
+        // a 2x2x2 cell of mostly stone, but only dirt touches the air above
+        let cells = downsample(Blocks::DEFINITIONS, SIZE, 2, |location| match location.y {
+            0 => Material::STONE as u16,
+            1 if location.x < 2 && location.z < 2 => Material::DIRT as u16,
+            1 => Material::STONE as u16,
+            _ => Material::AIR as u16
+        });
+        assert_eq!(cells.len(), 8);
+        assert_eq!(cells[0], Some(Material::DIRT as u16));
+
+        // the cell beside it along x has no dirt, and the cell above it is empty
+        assert_eq!(cells[4], Some(Material::STONE as u16));
+        assert_eq!(cells[2], None);
+    }
+
+    #[test]
+    fn cells_are_filled_from_half_of_their_blocks() {
+        let half = downsample(Blocks::DEFINITIONS, SIZE, 4, |location| if location.y < 2 { Material::STONE as u16 } else { Material::AIR as u16 });
+        assert_eq!(half, vec![Some(Material::STONE as u16)]);
+
+        // transparent blocks without level of detail tiles do not count towards filling a cell
+        let less = downsample(Blocks::DEFINITIONS, SIZE, 4, |location| if location.y < 1 || (location.y == 1 && location.x > 0) { Material::STONE as u16 } else { Material::GLASS as u16 });
+        assert_eq!(less, vec![None]);
+    }
+
+    #[test]
+    fn only_closed_sides_are_drawn_at_the_border() {
+        let (tiles, animations, models) = (TileNames::new(), TileAnimations::new(), BlockModels::new());
+        let resources = RenderResources { tiles: &tiles, animations: &animations, models: &models, tints: None };
+        let cells = vec![Some(Material::STONE as u16); 8];
+
+        // a solid box only shows faces where its neighbour is at a different level of detail
+        let open = mesh_cells(Blocks::DEFINITIONS, &cells, SIZE, 2, [false; 6], &Transform::default(), resources);
+        assert!(open.is_empty());
+
+        let closed = mesh_cells(Blocks::DEFINITIONS, &cells, SIZE, 2, [true, false, false, false, false, false], &Transform::default(), resources);
+        assert_eq!(closed.len(), 4 * 6);
+        for vertex in closed {
+            assert_eq!(vertex.position[1], 4.0);
+            assert_eq!(vertex.normal, [0.0, 1.0, 0.0]);
+            assert!(vertex.tex_coords.iter().all(|coord| (0.0 ..= 2.0).contains(coord)));
+        }
+    }
+
+    #[test]
+    fn seams_at_full_detail_are_open_to_the_first_transparent_material() {
+        let (tiles, animations, models) = (TileNames::new(), TileAnimations::new(), BlockModels::new());
+        let resources = RenderResources { tiles: &tiles, animations: &animations, models: &models, tints: None };
+
+        // the first material is solid stone, so the seam must be filled with air for the faces on it to show
+        let mut chunk = Chunk::<Blocks, Material, 2, 2, 2>::empty(0);
+        chunk.set_lod(ChunkLod::Full, [ChunkLod::Half, ChunkLod::Full, ChunkLod::Full, ChunkLod::Full, ChunkLod::Full, ChunkLod::Full]);
+        let groups = chunk.gen_material_vertices(resources);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].0 as u16, Material::STONE as u16);
+        assert_eq!(groups[0].1.len(), 4 * 6);
+        assert!(groups[0].1.iter().all(|vertex| vertex.normal == [0.0, 1.0, 0.0]));
+    }
+}
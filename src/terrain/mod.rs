@@ -1,3 +1,22 @@
+pub mod animations;
+pub mod atlas;
+pub mod block_entities;
+pub mod block_entity_models;
+pub mod block_models;
 pub mod blocks;
 pub mod chunk;
+pub mod connected;
+pub mod edits;
+pub mod lod;
 pub mod lookup;
+pub mod obj;
+pub mod occlusion;
+pub mod schematics;
+pub mod shapes;
+pub mod smooth;
+pub mod states;
+pub mod streaming;
+pub mod ticks;
+pub mod vertices;
+pub mod volumes;
+pub mod vox;
\ No newline at end of file
@@ -0,0 +1,232 @@
+use std::{collections::HashMap, fmt::Write, fs};
+
+use cgmath::*;
+
+use crate::terrain::{blocks::*, chunk::Chunk, vertices::ChunkVertex};
+
+/// Exports chunk meshes as a Wavefront OBJ file with an MTL file, so terrain can be opened in external tools.
+///
+/// Every block is meshed at full detail and faces are grouped by the material of their block.  All faces use one material that samples the block atlas image, where tiles are laid out left to right, then top to bottom like `BlockAtlas::load` expects.
+/// Atlases made with `BlockAtlasBuilder` have no such image, so write one with `BlockAtlasBuilder::to_sheet` before building the atlas.
+/// Tinted faces are written with vertex colours after their positions, which most tools support.  Vertices shared by faces are written once.
+/// OBJ has no triplanar mapping, so each triangle of a smooth surface samples its tile projected along the axis its normal points most along, clamped to one tile.
+///
+/// Example
+/// ```rust,ignore
+/// let (sheet, rows) = builder.to_sheet(16);
+/// sheet.save("terrain.png")?;
+///
+/// let mut export = ObjExport::new("terrain.png", 16, rows);
+/// export.add_chunks(streamer.chunks().map(|(_, chunk)| chunk), resources);
+/// export.save("terrain.obj", "terrain.mtl")?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ObjExport {
+    /// The path of the atlas image written into the MTL file, usually relative to the MTL file.
+    pub atlas: String,
+    /// The number of tiles across the atlas image.
+    pub columns: u32,
+    /// The number of tiles down the atlas image.
+    pub rows: u32,
+    positions: Vec<String>,
+    tex_coords: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    position_indices: HashMap<[u32; 6], usize>,
+    tex_coord_indices: HashMap<[u32; 2], usize>,
+    normal_indices: HashMap<[u32; 3], usize>,
+    groups: Vec<(String, Vec<[[usize; 3]; 3]>)>
+}
+
+impl ObjExport {
+    /// Creates an empty export that references the given atlas image.
+    ///
+    /// Arguments:
+    /// * atlas: impl Into<String> - The path of the atlas image written into the MTL file.
+    /// * columns: u32 - The number of tiles across the atlas image.
+    /// * rows: u32 - The number of tiles down the atlas image.
+    pub fn new(atlas: impl Into<String>, columns: u32, rows: u32) -> Self {
+        Self {
+            atlas: atlas.into(),
+            columns: columns.max(1),
+            rows: rows.max(1),
+            positions: Vec::new(),
+            tex_coords: Vec::new(),
+            normals: Vec::new(),
+            position_indices: HashMap::new(),
+            tex_coord_indices: HashMap::new(),
+            normal_indices: HashMap::new(),
+            groups: Vec::new()
+        }
+    }
+
+    /// Adds the mesh of the given chunk with its vertices transformed by the given matrix.
+    ///
+    /// Arguments:
+    /// * chunk: &Chunk<T, M, X, Y, Z> - The chunk to add.
+    /// * transform: Matrix4<f32> - The matrix that places the chunk in the world, usually `chunk.transform.to_mat()`.
+    /// * resources: RenderResources - The resources used to mesh the chunk.
+    pub fn add_chunk<T: BlockDefinitions<M>, M: MaterialDef + 'static, const X: usize, const Y: usize, const Z: usize>(&mut self, chunk: &Chunk<T, M, X, Y, Z>, transform: Matrix4<f32>, resources: RenderResources) {
+        for (material, vertices) in chunk.gen_material_vertices(resources) {
+            self.add_vertices(format!("{:?}", material), &vertices, transform);
+        }
+    }
+
+    /// Adds the meshes of the given chunks, each placed by its transform.
+    pub fn add_chunks<'c, T: BlockDefinitions<M> + 'c, M: MaterialDef + 'static, const X: usize, const Y: usize, const Z: usize>(&mut self, chunks: impl IntoIterator<Item = &'c Chunk<T, M, X, Y, Z>>, resources: RenderResources) {
+        for chunk in chunks { self.add_chunk(chunk, chunk.transform.to_mat(), resources); }
+    }
+
+    /// Adds a triangle list of chunk vertices transformed by the given matrix to the group with the given name.
+    pub fn add_vertices(&mut self, group: impl Into<String>, vertices: &[ChunkVertex], transform: Matrix4<f32>) {
+        let group = group.into();
+        let index = match self.groups.iter().position(|(name, _)| *name == group) {
+            Some(index) => index,
+            None => { self.groups.push((group, Vec::new())); self.groups.len() - 1 }
+        };
+
+        // normals are transformed by the inverse transpose so they stay perpendicular to scaled faces
+        let linear = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate());
+        let normal_matrix = linear.invert().map(|inverse| inverse.transpose()).unwrap_or(linear);
+
+        for triangle in vertices.chunks_exact(3) {
+            let positions = [0, 1, 2].map(|corner| (transform * Vector3::from(triangle[corner].position).extend(1.0)).truncate());
+            let tex_coords = if triangle[0].triplanar == 1 { triplanar_tex_coords(triangle, &positions) } else { [0, 1, 2].map(|corner| triangle[corner].tex_coords) };
+
+            let mut face = [[0; 3]; 3];
+            for (corner, ((vertex, position), tex_coords)) in face.iter_mut().zip(triangle.iter().zip(positions).zip(tex_coords)) {
+                let normal = (normal_matrix * Vector3::from(vertex.normal)).normalize();
+                *corner = [
+                    self.position_index(position.into(), vertex.color),
+                    self.tex_coord_index(self.atlas_tex_coords(vertex.layer, tex_coords)),
+                    self.normal_index(normal.into())
+                ];
+            }
+            self.groups[index].1.push(face);
+        }
+    }
+
+    /// Writes the OBJ file, which loads its materials from the given MTL file name.
+    pub fn to_obj(&self, mtl: &str) -> String {
+        let mut result = String::new();
+        let _ = writeln!(result, "mtllib {}", mtl);
+        self.positions.iter().for_each(|line| { let _ = writeln!(result, "{}", line); });
+        self.tex_coords.iter().for_each(|uv| { let _ = writeln!(result, "vt {} {}", uv[0], uv[1]); });
+        self.normals.iter().for_each(|normal| { let _ = writeln!(result, "vn {} {} {}", normal[0], normal[1], normal[2]); });
+        for (name, faces) in self.groups.iter() {
+            let _ = writeln!(result, "g {}", name);
+            let _ = writeln!(result, "usemtl atlas");
+            faces.iter().for_each(|face| {
+                let [a, b, c] = face.map(|[v, vt, vn]| format!("{}/{}/{}", v, vt, vn));
+                let _ = writeln!(result, "f {} {} {}", a, b, c);
+            });
+        }
+        result
+    }
+
+    /// Writes the MTL file with the single material that samples the atlas image.
+    pub fn to_mtl(&self) -> String {
+        format!("newmtl atlas\nKa 1 1 1\nKd 1 1 1\nKs 0 0 0\nd 1\nillum 1\nmap_Kd {}\n", self.atlas)
+    }
+
+    /// Saves the OBJ and MTL files to the given paths.  The OBJ file references the MTL file by its file name, so they should be saved in the same directory.
+    pub fn save(&self, obj: &str, mtl: &str) -> std::io::Result<()> {
+        let mtl_name = std::path::Path::new(mtl).file_name().and_then(|name| name.to_str()).unwrap_or(mtl);
+        fs::write(obj, self.to_obj(mtl_name))?;
+        fs::write(mtl, self.to_mtl())
+    }
+
+    // Gets the obj index of the given position and colour, writing it if it was not written before.  Obj indices start at 1.
+    fn position_index(&mut self, position: [f32; 3], color: [f32; 3]) -> usize {
+        let key = [position[0], position[1], position[2], color[0], color[1], color[2]].map(f32::to_bits);
+        if let Some(index) = self.position_indices.get(&key) { return *index }
+        let mut line = format!("v {} {} {}", position[0], position[1], position[2]);
+        if color != [1.0, 1.0, 1.0] { let _ = write!(line, " {} {} {}", color[0], color[1], color[2]); }
+        self.positions.push(line);
+        self.position_indices.insert(key, self.positions.len());
+        self.positions.len()
+    }
+
+    // Gets the obj index of the given texture coordinates, writing them if they were not written before.
+    fn tex_coord_index(&mut self, tex_coords: [f32; 2]) -> usize {
+        let key = tex_coords.map(f32::to_bits);
+        if let Some(index) = self.tex_coord_indices.get(&key) { return *index }
+        self.tex_coords.push(tex_coords);
+        self.tex_coord_indices.insert(key, self.tex_coords.len());
+        self.tex_coords.len()
+    }
+
+    // Gets the obj index of the given normal, writing it if it was not written before.
+    fn normal_index(&mut self, normal: [f32; 3]) -> usize {
+        let key = normal.map(f32::to_bits);
+        if let Some(index) = self.normal_indices.get(&key) { return *index }
+        self.normals.push(normal);
+        self.normal_indices.insert(key, self.normals.len());
+        self.normals.len()
+    }
+
+    // Converts tile local texture coordinates on the given layer into coordinates on the atlas image, with v going up like obj expects.
+    fn atlas_tex_coords(&self, layer: u32, tex_coords: [f32; 2]) -> [f32; 2] {
+        let column = (layer % self.columns) as f32;
+        let row = (layer / self.columns) as f32;
+        [(column + tex_coords[0]) / self.columns as f32, 1.0 - (row + tex_coords[1]) / self.rows as f32]
+    }
+}
+
+// Gets tile local texture coordinates for a triangle of a smooth surface by projecting its world positions along the axis its normal points most along, like the triplanar mapping of the terrain shader.
+// The coordinates are moved to start in the tile the triangle starts in and clamped to that tile, as they cannot repeat across the atlas image.
+fn triplanar_tex_coords(triangle: &[ChunkVertex], positions: &[Vector3<f32>; 3]) -> [[f32; 2]; 3] {
+    let normal = triangle.iter().fold(Vector3::zero(), |sum: Vector3<f32>, vertex| sum + Vector3::from(vertex.normal));
+    let projected = positions.map(|position| {
+        if normal.x.abs() >= normal.y.abs() && normal.x.abs() >= normal.z.abs() { [position.z, -position.y] }
+        else if normal.y.abs() >= normal.z.abs() { [position.x, position.z] }
+        else { [position.x, -position.y] }
+    });
+    let start = [0, 1].map(|axis| projected.iter().map(|uv| uv[axis]).fold(f32::INFINITY, f32::min).floor());
+    projected.map(|uv| [(uv[0] - start[0]).clamp(0.0, 1.0), (uv[1] - start[1]).clamp(0.0, 1.0)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use forte_engine::primitives::vertices::Vertex;
+    use crate::terrain::lookup;
+
+    fn lines<'a>(obj: &'a str, prefix: &str) -> Vec<&'a str> { obj.lines().filter(|line| line.starts_with(prefix)).collect() }
+
+    #[test]
+    fn shared_vertices_are_written_once() {
+        let vertices: Vec<ChunkVertex> = lookup::CUBE_TOP.iter().map(|vertex: &Vertex| ChunkVertex::from_vertex(vertex, 0)).collect();
+        let mut export = ObjExport::new("atlas.png", 1, 1);
+        export.add_vertices("top", &vertices, Matrix4::identity());
+        export.add_vertices("top", &vertices, Matrix4::identity());
+        let obj = export.to_obj("atlas.mtl");
+        assert_eq!((lines(&obj, "v ").len(), lines(&obj, "vt ").len(), lines(&obj, "vn ").len(), lines(&obj, "f ").len()), (4, 4, 1, 4));
+    }
+
+    #[test]
+    fn vertices_are_transformed() {
+        let vertices: Vec<ChunkVertex> = lookup::CUBE_NORTH.iter().map(|vertex: &Vertex| ChunkVertex::from_vertex(vertex, 0)).collect();
+        let transform = Matrix4::from_translation(Vector3::new(10.0, 0.0, 0.0)) * Matrix4::from_angle_y(Deg(90.0)) * Matrix4::from_nonuniform_scale(1.0, 1.0, 4.0);
+        let mut export = ObjExport::new("atlas.png", 1, 1);
+        export.add_vertices("north", &vertices, transform);
+        let obj = export.to_obj("atlas.mtl");
+
+        // the north face is pushed out to z = 4, then turned to face east at x = 14
+        for line in lines(&obj, "v ") { assert_eq!(line.split(' ').nth(1).unwrap().parse::<f32>().unwrap(), 14.0); }
+        let normal: Vec<f32> = lines(&obj, "vn ")[0].split(' ').skip(1).map(|value| value.parse().unwrap()).collect();
+        assert!((normal[0] - 1.0).abs() < 1e-6 && normal[1].abs() < 1e-6 && normal[2].abs() < 1e-6);
+    }
+
+    #[test]
+    fn smooth_triangles_are_projected_into_one_tile() {
+        let vertex = |position: [f32; 3]| ChunkVertex { position, tex_coords: [0.0, 0.0], normal: [0.0, 1.0, 0.0], layer: 1, animation: 0, color: [1.0, 1.0, 1.0], triplanar: 1 };
+        let vertices = [vertex([2.5, 3.0, 4.5]), vertex([3.5, 3.2, 4.5]), vertex([2.5, 3.1, 4.75])];
+        let mut export = ObjExport::new("atlas.png", 2, 1);
+        export.add_vertices("smooth", &vertices, Matrix4::identity());
+        let obj = export.to_obj("atlas.mtl");
+
+        // projected down y, starting in the tile at (2, 4) of the second column
+        let uvs: Vec<Vec<f32>> = lines(&obj, "vt ").iter().map(|line| line.split(' ').skip(1).map(|value| value.parse().unwrap()).collect()).collect();
+        assert_eq!(uvs, vec![vec![0.75, 0.5], vec![1.0, 0.5], vec![0.75, 0.25]]);
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::{HashSet, VecDeque};
+
+use cgmath::*;
+
+use crate::terrain::states::Direction;
+
+/// Which pairs of the 6 sides of a chunk can see each other through connected transparent blocks.  This is computed when a chunk is meshed and is used by `visible_chunks` to skip chunks hidden behind solid ground, like caves under the surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkVisibility {
+    bits: u64
+}
+
+impl Default for ChunkVisibility {
+    fn default() -> Self { Self::all() }
+}
+
+impl ChunkVisibility {
+    /// A visibility where no sides can see each other, like a fully solid chunk.
+    pub fn none() -> Self { Self { bits: 0 } }
+
+    /// A visibility where every side can see every other, like an empty chunk.
+    pub fn all() -> Self { Self { bits: (1 << 36) - 1 } }
+
+    /// Returns true if the two given sides can see each other.
+    pub fn connected(&self, a: Direction, b: Direction) -> bool { self.bits & (1 << (a.index() * 6 + b.index())) != 0 }
+
+    /// Marks the two given sides as able to see each other.
+    pub fn connect(&mut self, a: Direction, b: Direction) {
+        self.bits |= 1 << (a.index() * 6 + b.index());
+        self.bits |= 1 << (b.index() * 6 + a.index());
+    }
+
+    /// Computes the visibility of a box of blocks by flood filling its transparent blocks.  Every pair of sides touched by the same connected group of transparent blocks can see each other.
+    ///
+    /// Arguments:
+    /// * size: Vector3<usize> - The size of the box of blocks.
+    /// * is_transparent: impl Fn(Vector3<usize>) -> bool - Returns true if the block at the given location can be seen through.
+    pub fn compute(size: Vector3<usize>, is_transparent: impl Fn(Vector3<usize>) -> bool) -> Self {
+        let index = |location: Vector3<usize>| (location.x * size.y + location.y) * size.z + location.z;
+        let mut visited = vec![false; size.x * size.y * size.z];
+        let mut result = Self::none();
+
+        for x in 0 .. size.x {
+            for y in 0 .. size.y {
+                for z in 0 .. size.z {
+                    let start = Vector3 { x, y, z };
+                    if visited[index(start)] || !is_transparent(start) { continue }
+
+                    // fill this group of transparent blocks, collecting the sides it touches
+                    let mut sides: Vec<Direction> = Vec::new();
+                    let mut stack = vec![start];
+                    visited[index(start)] = true;
+                    while let Some(location) = stack.pop() {
+                        for direction in Direction::ALL {
+                            let normal = direction.normal().cast::<i32>().unwrap();
+                            let next = Vector3 { x: location.x as i32 + normal.x, y: location.y as i32 + normal.y, z: location.z as i32 + normal.z };
+                            if next.x < 0 || next.y < 0 || next.z < 0 || next.x >= size.x as i32 || next.y >= size.y as i32 || next.z >= size.z as i32 {
+                                if !sides.contains(&direction) { sides.push(direction); }
+                                continue
+                            }
+
+                            let next = next.cast::<usize>().unwrap();
+                            if visited[index(next)] || !is_transparent(next) { continue }
+                            visited[index(next)] = true;
+                            stack.push(next);
+                        }
+                    }
+
+                    for a in &sides { for b in &sides { result.connect(*a, *b); } }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Finds the chunks that may be seen from the chunk containing the camera by a breadth first search through the sides of chunks that can see each other, skipping chunks that are only reachable through solid blocks.
+/// The search never turns back in a direction opposite to one it has already travelled, so it only finds chunks that can be seen along a roughly straight line of sight.
+///
+/// Arguments:
+/// * start: Vector3<i32> - The grid position of the chunk containing the camera.
+/// * visibility: impl Fn(Vector3<i32>) -> Option<ChunkVisibility> - Gets the visibility of the chunk at the given grid position, or `None` if the chunk should not be searched, like when it is not loaded or is outside of the cameras `Frustum`.
+///
+/// Returns the grid positions of the chunks that may be seen, closest first, starting with `start`.
+pub fn visible_chunks(start: Vector3<i32>, visibility: impl Fn(Vector3<i32>) -> Option<ChunkVisibility>) -> Vec<Vector3<i32>> {
+    let mut result = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+
+    // each entry is the chunk, the side it was entered from and the directions travelled to reach it
+    let start_visibility = match visibility(start) { Some(visibility) => visibility, None => return result };
+    queue.push_back((start, start_visibility, None, 0u8));
+    visited.insert(start);
+
+    while let Some((position, chunk, entered, travelled)) = queue.pop_front() {
+        result.push(position);
+
+        for direction in Direction::ALL {
+            // do not turn back, and only leave through sides that can be seen from the side this chunk was entered from
+            if travelled & (1 << direction.opposite().index()) != 0 { continue }
+            if let Some(entered) = entered { if !chunk.connected(entered, direction) { continue } }
+
+            let next = position + direction.normal().cast::<i32>().unwrap();
+            if visited.contains(&next) { continue }
+            let next_visibility = match visibility(next) { Some(visibility) => visibility, None => continue };
+            visited.insert(next);
+            queue.push_back((next, next_visibility, Some(direction.opposite()), travelled | (1 << direction.index())));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: Vector3<usize> = Vector3 { x: 8, y: 8, z: 8 };
+
+    #[test]
+    fn solid_and_empty_boxes() {
+        assert_eq!(ChunkVisibility::compute(SIZE, |_| false), ChunkVisibility::none());
+        assert_eq!(ChunkVisibility::compute(SIZE, |_| true), ChunkVisibility::all());
+    }
+
+    #[test]
+    fn walls_split_the_sides() {
+        // a solid floor at y = 4 separates the air above from the air below
+        let visibility = ChunkVisibility::compute(SIZE, |location| location.y != 4);
+        assert!(visibility.connected(Direction::Above, Direction::North));
+        assert!(visibility.connected(Direction::Below, Direction::East));
+        assert!(visibility.connected(Direction::North, Direction::South));
+        assert!(!visibility.connected(Direction::Above, Direction::Below));
+    }
+
+    #[test]
+    fn tunnels_connect_their_ends() {
+        // a tunnel along x through solid ground only connects east and west
+        let visibility = ChunkVisibility::compute(SIZE, |location| location.y == 3 && location.z == 3);
+        assert!(visibility.connected(Direction::East, Direction::West));
+        assert!(visibility.connected(Direction::West, Direction::East));
+        for direction in [Direction::Above, Direction::Below, Direction::North, Direction::South] {
+            assert!(!visibility.connected(Direction::East, direction));
+            assert!(!visibility.connected(direction, direction));
+        }
+    }
+
+    #[test]
+    fn search_stops_at_solid_chunks() {
+        // a row of open chunks along x with a solid chunk at x = 2
+        let found = visible_chunks(Vector3::zero(), |position| {
+            if position.y != 0 || position.z != 0 || !(-3 ..= 3).contains(&position.x) { return None }
+            Some(if position.x == 2 { ChunkVisibility::none() } else { ChunkVisibility::all() })
+        });
+        assert_eq!(found[0], Vector3::zero());
+        assert!(found.contains(&Vector3::new(-3, 0, 0)));
+        assert!(found.contains(&Vector3::new(2, 0, 0)));
+        assert!(!found.contains(&Vector3::new(3, 0, 0)));
+    }
+}
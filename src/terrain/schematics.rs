@@ -0,0 +1,245 @@
+use std::{fmt::Display, fs};
+
+use cgmath::*;
+use serde::*;
+
+use crate::terrain::{block_entities::SavedBlockEntity, blocks::*, edits::{BlockWorld, EditTransaction}, volumes::{rotate_location, Clipboard, Region}};
+
+/// The errors that can occur while saving, loading or pasting a `Schematic`.
+#[derive(Debug)]
+pub enum SchematicError {
+    /// A schematic file could not be read or written.
+    Io(String, std::io::Error),
+    /// A schematic could not be parsed or written as JSON.
+    Json(serde_json::Error),
+    /// The size of a schematic has an axis of 0 blocks, or has too many blocks to count.
+    InvalidSize([usize; 3]),
+    /// The number of blocks in a schematic does not match its size.
+    WrongSize { expected: usize, found: usize },
+    /// A block entity is positioned outside of the size of the schematic.
+    EntityOutOfBounds([usize; 3]),
+    /// A block uses a palette index that is not in the palette.
+    MissingPalette(u16),
+    /// A material in the palette is not defined by the `BlockDefinitions` the schematic is pasted with.
+    UnknownMaterial(String)
+}
+
+impl Display for SchematicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "Could not access schematic file {}: {}", path, err),
+            Self::Json(err) => write!(f, "Could not parse schematic: {}", err),
+            Self::InvalidSize(size) => write!(f, "Schematic has invalid size {:?}", size),
+            Self::WrongSize { expected, found } => write!(f, "Schematic should have {} blocks but has {}", expected, found),
+            Self::EntityOutOfBounds(position) => write!(f, "Schematic block entity at {:?} is outside of the schematic", position),
+            Self::MissingPalette(index) => write!(f, "Schematic block uses palette index {} which is not in the palette", index),
+            Self::UnknownMaterial(name) => write!(f, "Schematic uses material {} which is not defined", name)
+        }
+    }
+}
+
+impl std::error::Error for SchematicError {}
+
+/// A saved block structure, like a house or a dungeon, that can be built once and pasted into terrain many times.
+///
+/// Materials are stored in a palette by the name of their `MaterialDef` variant, so schematics keep working when new materials are added or existing ones are reordered.  Blocks that were not loaded when the schematic was copied are stored as `None` and are skipped when it is pasted.
+///
+/// Example
+/// ```rust,ignore
+/// let house = Schematic::copy(&streamer, Region::new(corner_a, corner_b));
+/// house.save("assets/house.json")?;
+///
+/// // later, build the house turned a quarter turn
+/// let house = Schematic::load("assets/house.json")?;
+/// let mut transaction = EditTransaction::new();
+/// transaction.paste_schematic::<Blocks>(&mut streamer, &house, Vector3 { x: 32, y: 10, z: 0 }, 1)?;
+/// history.commit(transaction);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schematic {
+    /// The size of the schematic in blocks.
+    pub size: [usize; 3],
+    /// The names of the materials used by the blocks.
+    pub palette: Vec<String>,
+    /// The palette index and data of every block, in x, then y, then z order.
+    pub blocks: Vec<Option<(u16, u16)>>,
+    /// The saved block entities, with positions local to the schematic.
+    #[serde(default)]
+    pub entities: Vec<SavedBlockEntity>
+}
+
+impl Schematic {
+    /// Copies the blocks and block entities in the given region of the world.
+    ///
+    /// Arguments:
+    /// * world: &impl BlockWorld<M> - The world to copy from.
+    /// * region: Region - The region of world positions to copy.
+    pub fn copy<M: MaterialDef + 'static>(world: &impl BlockWorld<M>, region: Region) -> Self {
+        let mut schematic = Self::from_clipboard(&Clipboard::copy(world, region));
+        schematic.entities = region.iter()
+            .filter_map(|position| Some(SavedBlockEntity {
+                position: (position - region.min).cast::<usize>().unwrap().into(),
+                data: world.save_block_entity_at(position)?
+            }))
+            .collect();
+        schematic
+    }
+
+    /// Creates a schematic from the blocks of the given clipboard, with no block entities.
+    pub fn from_clipboard<M: MaterialDef + 'static>(clipboard: &Clipboard<M>) -> Self {
+        let size = clipboard.size();
+        let mut palette: Vec<String> = Vec::new();
+        let mut blocks = vec![None; size.x * size.y * size.z];
+        for (location, (material, data)) in clipboard.iter() {
+            let name = material_name(material);
+            let index = match palette.iter().position(|entry| *entry == name) {
+                Some(index) => index,
+                None => { palette.push(name); palette.len() - 1 }
+            };
+            blocks[(location.x * size.y + location.y) * size.z + location.z] = Some((index as u16, data));
+        }
+        Self { size: size.into(), palette, blocks, entities: Vec::new() }
+    }
+
+    /// Converts the blocks of this schematic to a clipboard, looking up the materials of the palette by name in the given `BlockDefinitions`.
+    pub fn to_clipboard<T: BlockDefinitions<M>, M: MaterialDef + 'static>(&self) -> Result<Clipboard<M>, SchematicError> {
+        self.validate()?;
+        let size = Vector3::from(self.size);
+
+        let materials = self.palette.iter()
+            .map(|name| {
+                T::DEFINITIONS.iter()
+                    .find(|def| material_name(def.material) == *name)
+                    .map(|def| def.material)
+                    .ok_or_else(|| SchematicError::UnknownMaterial(name.clone()))
+            })
+            .collect::<Result<Vec<M>, SchematicError>>()?;
+
+        let mut clipboard = Clipboard::empty(size);
+        for (index, block) in self.blocks.iter().enumerate() {
+            let (palette, data) = match block { Some(block) => *block, None => continue };
+            let material = *materials.get(palette as usize).ok_or(SchematicError::MissingPalette(palette))?;
+            let location = Vector3 { x: index / (size.y * size.z), y: (index / size.z) % size.y, z: index % size.z };
+            clipboard.set(location, Some((material, data)));
+        }
+        Ok(clipboard)
+    }
+
+    /// Checks that the size of this schematic has no empty axis, that it has one entry for every block, and that its block entities are inside of it.  This is done when a schematic is parsed or converted to a clipboard.
+    pub fn validate(&self) -> Result<(), SchematicError> {
+        if self.size.contains(&0) { return Err(SchematicError::InvalidSize(self.size)) }
+        let expected = self.size.iter().try_fold(1usize, |total, axis| total.checked_mul(*axis)).ok_or(SchematicError::InvalidSize(self.size))?;
+        if self.blocks.len() != expected { return Err(SchematicError::WrongSize { expected, found: self.blocks.len() }) }
+
+        let outside = self.entities.iter().find(|entity| entity.position.iter().zip(self.size).any(|(position, size)| *position >= size));
+        if let Some(entity) = outside { return Err(SchematicError::EntityOutOfBounds(entity.position)) }
+        Ok(())
+    }
+
+    /// Loads a schematic from a JSON file at the given path.
+    pub fn load(path: &str) -> Result<Self, SchematicError> {
+        let json = fs::read_to_string(path).map_err(|err| SchematicError::Io(path.to_string(), err))?;
+        Self::from_json(&json)
+    }
+
+    /// Saves this schematic as a JSON file at the given path.
+    pub fn save(&self, path: &str) -> Result<(), SchematicError> {
+        fs::write(path, self.to_json()?).map_err(|err| SchematicError::Io(path.to_string(), err))
+    }
+
+    /// Parses a schematic from a JSON string, checking it with `validate`.
+    pub fn from_json(json: &str) -> Result<Self, SchematicError> {
+        let schematic: Self = serde_json::from_str(json).map_err(SchematicError::Json)?;
+        schematic.validate()?;
+        Ok(schematic)
+    }
+
+    /// Writes this schematic as a JSON string.
+    pub fn to_json(&self) -> Result<String, SchematicError> { serde_json::to_string(self).map_err(SchematicError::Json) }
+}
+
+impl <M: MaterialDef + 'static> EditTransaction<M> {
+    /// Pastes the given schematic turned around the Y axis, then restores its block entities.  Air (material 0) in the schematic is skipped.
+    ///
+    /// Arguments:
+    /// * world: &mut impl BlockWorld<M> - The world to paste into.
+    /// * schematic: &Schematic - The schematic to paste.
+    /// * origin: Vector3<i32> - The world position of the minimum corner of the turned schematic.
+    /// * turns: u32 - The number of quarter turns around the Y axis, where one turn takes north to east.
+    ///
+    /// Returns the number of blocks that were loaded and set, or an error if the schematic does not match the given `BlockDefinitions`.
+    pub fn paste_schematic<T: BlockDefinitions<M>>(&mut self, world: &mut impl BlockWorld<M>, schematic: &Schematic, origin: Vector3<i32>, turns: u32) -> Result<usize, SchematicError> {
+        let clipboard = schematic.to_clipboard::<T, M>()?.rotate_y::<T>(turns);
+        let count = self.paste(world, &clipboard, origin, true);
+
+        let size = Vector3::from(schematic.size);
+        for entity in schematic.entities.iter() {
+            let location = rotate_location(Vector3::from(entity.position), size, turns);
+            world.load_block_entity_at(origin + location.cast::<i32>().unwrap(), entity.data.clone());
+        }
+        Ok(count)
+    }
+}
+
+// The name of a material in a schematic palette, which is the name of its variant.
+fn material_name<M: MaterialDef + 'static>(material: M) -> String { format!("{:?}", material) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::define_blocks_materials;
+
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        AIR => { transparent: true, renderer: BlockRenderer::None },
+        STONE => { transparent: false, renderer: BlockRenderer::None },
+        CHEST => { transparent: false, renderer: BlockRenderer::None }
+    ]);
+
+    fn schematic() -> Schematic {
+        let mut clipboard = Clipboard::empty(Vector3::new(2, 3, 2));
+        clipboard.set(Vector3::new(0, 0, 0), Some((Material::STONE, 0)));
+        clipboard.set(Vector3::new(1, 2, 1), Some((Material::CHEST, 5)));
+        clipboard.set(Vector3::new(1, 0, 1), Some((Material::AIR, 0)));
+        let mut schematic = Schematic::from_clipboard(&clipboard);
+        schematic.entities.push(SavedBlockEntity { position: [1, 2, 1], data: serde_json::json!({ "items": 3 }) });
+        schematic
+    }
+
+    fn blocks(clipboard: &Clipboard<Material>) -> Vec<(Vector3<usize>, u16, u16)> {
+        clipboard.iter().map(|(location, (material, data))| (location, material.into(), data)).collect()
+    }
+
+    #[test]
+    fn schematics_round_trip_through_json() {
+        let schematic = schematic();
+        assert_eq!(schematic.palette, vec!["STONE", "AIR", "CHEST"]);
+        let loaded = Schematic::from_json(&schematic.to_json().unwrap()).unwrap();
+        assert_eq!(loaded, schematic);
+        assert_eq!(blocks(&loaded.to_clipboard::<Blocks, Material>().unwrap()), vec![
+            (Vector3::new(0, 0, 0), Material::STONE as u16, 0),
+            (Vector3::new(1, 0, 1), Material::AIR as u16, 0),
+            (Vector3::new(1, 2, 1), Material::CHEST as u16, 5)
+        ]);
+    }
+
+    #[test]
+    fn invalid_schematics_are_rejected() {
+        let mut outside = schematic();
+        outside.entities[0].position = [1, 3, 1];
+        assert!(matches!(Schematic::from_json(&outside.to_json().unwrap()), Err(SchematicError::EntityOutOfBounds([1, 3, 1]))));
+        assert!(matches!(outside.to_clipboard::<Blocks, Material>(), Err(SchematicError::EntityOutOfBounds(_))));
+
+        let empty = Schematic { size: [4, 0, 4], palette: Vec::new(), blocks: Vec::new(), entities: Vec::new() };
+        assert!(matches!(empty.validate(), Err(SchematicError::InvalidSize([4, 0, 4]))));
+        let huge = Schematic { size: [usize::MAX, 2, 1], ..empty };
+        assert!(matches!(huge.validate(), Err(SchematicError::InvalidSize(_))));
+
+        let mut short = schematic();
+        short.blocks.pop();
+        assert!(matches!(short.validate(), Err(SchematicError::WrongSize { expected: 12, found: 11 })));
+
+        let mut unknown = schematic();
+        unknown.palette[0] = "GLASS".to_string();
+        assert!(matches!(unknown.to_clipboard::<Blocks, Material>(), Err(SchematicError::UnknownMaterial(name)) if name == "GLASS"));
+    }
+}
@@ -0,0 +1,440 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use cgmath::*;
+use forte_engine::primitives::vertices::Vertex;
+
+use crate::terrain::{block_models::face_uv, blocks::snap, states::{Axis, BlockState, Direction, Half}};
+
+/// The built in shapes drawn by the `Slab`, `Stairs`, `Slope` and `Pillar` renderers of `BlockRenderer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockShape {
+    /// Half of a block, in the bottom or top of the block space given by the "half" property.
+    Slab,
+    /// A slab with a quarter block step on top, where the "facing" property is the direction of the high back of the step and the "half" property flips the stairs upside down.  Stairs bend into corners to join neighbouring stairs, see `StairsCorner`.
+    Stairs,
+    /// A wedge that rises from the front to the top of the back of the block, where the "facing" property is the direction of the high back and the "half" property flips the wedge upside down.
+    Slope,
+    /// A post half a block wide running through the center of the block along the "axis" property.
+    Pillar
+}
+
+/// How a stairs block bends to join the stairs next to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StairsCorner {
+    /// The step runs straight across the block.
+    Straight,
+    /// The step turns inwards, so the quarter of the top half in front of the step on the side of the given direction is also filled.
+    Inner(Direction),
+    /// The step turns outwards, so only the quarter of the top half at the back of the step on the side of the given direction is filled.
+    Outer(Direction)
+}
+
+impl StairsCorner {
+    /// Finds how a stairs block bends from the stairs next to it.  Stairs behind the block facing across it make an outer corner, otherwise stairs in front of the block facing across it make an inner corner.  Stairs only join stairs of the same half, and not if that would break a straight run of stairs facing the same way.
+    ///
+    /// Arguments:
+    /// * state: BlockState - The state of the stairs block.
+    /// * stairs: impl Fn(Direction) -> Option<BlockState> - Gets the state of the block next to the stairs in the given horizontal direction if it is also stairs, or `None` otherwise.
+    pub fn find(state: BlockState, stairs: impl Fn(Direction) -> Option<BlockState>) -> Self {
+        let facing = state.facing().unwrap_or(Direction::North);
+        let half = state.half().unwrap_or(Half::Bottom);
+
+        // gets the facing of neighbouring stairs of the same half
+        let joined = |direction: Direction| stairs(direction).filter(|other| other.half().unwrap_or(Half::Bottom) == half).map(|other| other.facing().unwrap_or(Direction::North));
+        let can_bend = |direction: Direction| joined(direction) != Some(facing);
+
+        if let Some(behind) = joined(facing) {
+            if behind.axis() != facing.axis() && can_bend(behind.opposite()) { return Self::Outer(behind) }
+        }
+        if let Some(front) = joined(facing.opposite()) {
+            if front.axis() != facing.axis() && can_bend(front) { return Self::Inner(front) }
+        }
+        Self::Straight
+    }
+}
+
+/// The part of one side of the block space covered by a face or a shape, as a 4x4 grid of cells across the side.
+///
+/// Cells are numbered by the world axes across the side, so both blocks on either side of a face agree on them.  For sides facing along X the cells go across Z then Y, for Y across X then Z, and for Z across X then Y.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SideCover {
+    /// One bit for each cell.  For a face these are the cells the face touches, and for a shape these are the cells it completely fills.
+    pub cells: u16,
+    /// The cells touched by a triangle face on this side, or 0 if there is none.  Matching triangles of neighbouring slopes hide each other.
+    pub triangle: u16
+}
+
+impl SideCover {
+    /// A side that is completely covered, like the side of a full block.
+    pub const FULL: Self = Self { cells: u16::MAX, triangle: 0 };
+
+    /// Returns true if a face covering `face` is hidden behind a neighbour covering this.
+    pub fn hides(&self, face: SideCover) -> bool {
+        face.cells & !self.cells == 0 || (face.triangle != 0 && face.triangle == self.triangle)
+    }
+}
+
+/// A flat face of a `BlockShape`.
+#[derive(Debug, Clone)]
+pub struct ShapeFace {
+    /// The corners of the face in order counter clockwise when looking at its front.
+    pub corners: Vec<Vertex>,
+    /// The index of the tile of this face, 0 for the above tile, 1 for the below tile and 2 for the side tile.
+    pub tile: usize,
+    /// The side of the block space this face lies on, or `None` if it is inside the block space and can never be hidden by a neighbour.
+    pub side: Option<Direction>
+}
+
+impl ShapeFace {
+    // Creates a face from its corners, turning them counter clockwise around the given normal.
+    fn new(mut points: Vec<Vector3<f32>>, normal: Vector3<f32>, tile: usize, side: Option<Direction>) -> Self {
+        if (points[1] - points[0]).cross(points[2] - points[0]).dot(normal) < 0.0 { points.reverse(); }
+        let corners = points.into_iter().map(|position| Vertex { position: position.into(), tex_coords: [0.0, 0.0], normal: normal.into() }).collect();
+        Self { corners, tile, side }
+    }
+
+    // Creates a rectangle face on the plane at the given depth along the axis of the given direction, from the given minimum to maximum across the plane.  See `SideCover` for the order of the axes across the plane.
+    fn rectangle(direction: Direction, depth: f32, min: Vector2<f32>, max: Vector2<f32>, tile: usize) -> Self {
+        let axis = direction.axis();
+        let points = vec![
+            plane_point(axis, depth, min.x, min.y),
+            plane_point(axis, depth, max.x, min.y),
+            plane_point(axis, depth, max.x, max.y),
+            plane_point(axis, depth, min.x, max.y)
+        ];
+        let outer = if direction.normal().sum() > 0.0 { 1.0 } else { 0.0 };
+        Self::new(points, direction.normal(), tile, if depth == outer { Some(direction) } else { None })
+    }
+
+    /// Splits this face into triangles for rendering.
+    pub fn triangles(&self) -> Vec<Vertex> {
+        (1 .. self.corners.len() - 1).flat_map(|index| [self.corners[0], self.corners[index], self.corners[index + 1]]).collect()
+    }
+
+    /// The cells of its side this face touches, see `SideCover`.  Faces that are not on a side touch nothing.
+    pub fn cover(&self) -> SideCover {
+        let side = match self.side { Some(side) => side, None => return SideCover::default() };
+        let polygon = self.polygon(side.axis());
+        let cells = cells_where(|corners| corners.iter().any(|corner| contains(&polygon, *corner)), 0.1);
+        SideCover { cells, triangle: if self.corners.len() == 3 { cells } else { 0 } }
+    }
+
+    // The cells of its side this face completely fills.
+    fn filled(&self, axis: Axis) -> u16 {
+        let polygon = self.polygon(axis);
+        cells_where(|corners| corners.iter().all(|corner| contains(&polygon, *corner)), 0.0)
+    }
+
+    // The corners of this face projected onto the plane across the given axis.
+    fn polygon(&self, axis: Axis) -> Vec<Vector2<f32>> {
+        self.corners.iter().map(|corner| plane_coords(axis, Vector3::from(corner.position))).collect()
+    }
+}
+
+/// Generates the faces of the given shape in the given state, placed in a 1x1x1 block space.
+///
+/// Arguments:
+/// * shape: BlockShape - The shape to generate.
+/// * state: BlockState - The state of the block, which gives the "facing", "half" and "axis" properties the shape uses.
+/// * corner: StairsCorner - How stairs bend to join their neighbours, ignored by other shapes.
+pub fn shape_faces(shape: BlockShape, state: BlockState, corner: StairsCorner) -> Vec<ShapeFace> {
+    let half = state.half().unwrap_or(Half::Bottom);
+    let mut faces = match shape {
+        BlockShape::Slab => octant_faces(|octant| (octant.y == 0) == (half == Half::Bottom)),
+        BlockShape::Stairs => {
+            // the back of the step is filled on top of a slab, turned by the corner
+            let back = |octant: Vector3<usize>, direction: Direction| {
+                let normal = direction.normal();
+                (octant.x as f32 - 0.5) * normal.x + (octant.z as f32 - 0.5) * normal.z > 0.0
+            };
+            let facing = state.facing().unwrap_or(Direction::North);
+            octant_faces(|octant| {
+                if (octant.y == 0) == (half == Half::Bottom) { return true }
+                match corner {
+                    StairsCorner::Straight => back(octant, facing),
+                    StairsCorner::Inner(direction) => back(octant, facing) || back(octant, direction),
+                    StairsCorner::Outer(direction) => back(octant, facing) && back(octant, direction)
+                }
+            })
+        },
+        BlockShape::Slope => {
+            let mut faces = slope_faces();
+            if half == Half::Top { flip_faces(&mut faces); }
+            faces
+        },
+        BlockShape::Pillar => vec![
+            ShapeFace::rectangle(Direction::Above, 1.0, Vector2::from_value(0.25), Vector2::from_value(0.75), 0),
+            ShapeFace::rectangle(Direction::Below, 0.0, Vector2::from_value(0.25), Vector2::from_value(0.75), 1),
+            ShapeFace::rectangle(Direction::North, 0.75, Vector2 { x: 0.25, y: 0.0 }, Vector2 { x: 0.75, y: 1.0 }, 2),
+            ShapeFace::rectangle(Direction::South, 0.25, Vector2 { x: 0.25, y: 0.0 }, Vector2 { x: 0.75, y: 1.0 }, 2),
+            ShapeFace::rectangle(Direction::East, 0.75, Vector2 { x: 0.25, y: 0.0 }, Vector2 { x: 0.75, y: 1.0 }, 2),
+            ShapeFace::rectangle(Direction::West, 0.25, Vector2 { x: 0.25, y: 0.0 }, Vector2 { x: 0.75, y: 1.0 }, 2)
+        ]
+    };
+
+    // texture faces by their position before they are rotated, so textures turn with the shape
+    for face in faces.iter_mut() {
+        let normal = Vector3::from(face.corners[0].normal);
+        let direction = if normal.y > 0.1 { Direction::Above } else if normal.y < -0.1 { Direction::Below } else { Direction::from_normal(normal) };
+        for corner in face.corners.iter_mut() {
+            let (u, v) = face_uv(direction, Vector3::from(corner.position));
+            corner.tex_coords = [u, v];
+        }
+    }
+
+    // stairs and slabs are built in place, other shapes are rotated from facing north or along the Y axis
+    if matches!(shape, BlockShape::Slope | BlockShape::Pillar) {
+        let rotation = state.rotation();
+        let center = Vector3::from_value(0.5);
+        for face in faces.iter_mut() {
+            for corner in face.corners.iter_mut() {
+                let position = rotation * (Vector3::from(corner.position) - center) + center;
+                let normal = rotation * Vector3::from(corner.normal);
+                corner.position = position.map(snap).into();
+                corner.normal = normal.map(snap).into();
+            }
+            face.side = face.side.map(|side| Direction::from_normal(rotation * side.normal()));
+        }
+    }
+
+    faces
+}
+
+/// Gets how much of the given side of its block space the given shape fills, which hides the faces of the neighbour on that side.  See `SideCover`.
+///
+/// Stairs are treated as filling only what they fill in every way they can bend, since the neighbours that decide their corner are not known.
+pub fn side_cover(shape: BlockShape, state: BlockState, side: Direction) -> SideCover {
+    // the faces only depend on the shape and the "facing", "half" and "axis" properties, so the covers of all sides are worked out once for each of those and kept
+    thread_local! {
+        static COVERS: RefCell<HashMap<SideCoverKey, [SideCover; 6]>> = RefCell::new(HashMap::new());
+    }
+
+    let key = (shape, state.facing(), state.half(), state.axis());
+    COVERS.with(|covers| *covers.borrow_mut().entry(key).or_insert_with(|| side_covers(shape, state)))[side.index()]
+}
+
+// The shape and the properties its faces depend on, see `side_cover`.
+type SideCoverKey = (BlockShape, Option<Direction>, Option<Half>, Option<Axis>);
+
+// Gets how much of each side of its block space the given shape fills, in the order of `Direction::ALL`.  See `side_cover`.
+fn side_covers(shape: BlockShape, state: BlockState) -> [SideCover; 6] {
+    let corners = match shape {
+        BlockShape::Stairs => {
+            let facing = state.facing().unwrap_or(Direction::North);
+            let mut corners = vec![StairsCorner::Straight];
+            for direction in Direction::HORIZONTAL.into_iter().filter(|direction| direction.axis() != facing.axis()) {
+                corners.push(StairsCorner::Inner(direction));
+                corners.push(StairsCorner::Outer(direction));
+            }
+            corners
+        },
+        _ => vec![StairsCorner::Straight]
+    };
+
+    let mut covers: Option<[SideCover; 6]> = None;
+    for corner in corners {
+        let faces = shape_faces(shape, state, corner);
+        let cover = Direction::ALL.map(|side| {
+            let on_side = || faces.iter().filter(|face| face.side == Some(side));
+            let cells = on_side().fold(0, |cells, face| cells | face.filled(side.axis()));
+            let triangle = on_side().find(|face| face.corners.len() == 3).map_or(0, |face| face.cover().triangle);
+            SideCover { cells, triangle }
+        });
+
+        // keep only what is filled in every corner
+        covers = Some(match covers {
+            Some(covers) => std::array::from_fn(|index| {
+                let (a, b) = (covers[index], cover[index]);
+                SideCover { cells: a.cells & b.cells, triangle: if a.triangle == b.triangle { a.triangle } else { 0 } }
+            }),
+            None => cover
+        });
+    }
+    covers.unwrap_or_default()
+}
+
+// Generates the faces of the octants of the block space that are filled but not touching another filled octant, merging faces on the same plane into rectangles.
+fn octant_faces(filled: impl Fn(Vector3<usize>) -> bool) -> Vec<ShapeFace> {
+    let octant = |axis: Axis, layer: usize, u: usize, v: usize| match axis {
+        Axis::X => Vector3 { x: layer, y: v, z: u },
+        Axis::Y => Vector3 { x: u, y: layer, z: v },
+        Axis::Z => Vector3 { x: u, y: v, z: layer }
+    };
+
+    let mut faces = Vec::new();
+    for direction in Direction::ALL {
+        let axis = direction.axis();
+        let positive = direction.normal().sum() > 0.0;
+        for layer in 0 .. 2usize {
+            // find the octants of this layer with a face showing in this direction, which are those on the outside of the block or in front of an empty octant
+            let next = if positive { Some(layer + 1).filter(|next| *next < 2) } else { layer.checked_sub(1) };
+            let mut showing = [[false; 2]; 2];
+            for (u, row) in showing.iter_mut().enumerate() {
+                for (v, showing) in row.iter_mut().enumerate() {
+                    *showing = filled(octant(axis, layer, u, v)) && next.is_none_or(|next| !filled(octant(axis, next, u, v)));
+                }
+            }
+
+            let depth = if positive { (layer + 1) as f32 * 0.5 } else { layer as f32 * 0.5 };
+            let tile = match direction { Direction::Above => 0, Direction::Below => 1, _ => 2 };
+            for (min, max) in rectangles(showing) {
+                faces.push(ShapeFace::rectangle(direction, depth, min.cast::<f32>().unwrap() * 0.5, max.cast::<f32>().unwrap() * 0.5, tile));
+            }
+        }
+    }
+    faces
+}
+
+// Splits the set cells of a 2x2 grid into rectangles, growing each rectangle across u first, then v.
+fn rectangles(mut cells: [[bool; 2]; 2]) -> Vec<(Vector2<usize>, Vector2<usize>)> {
+    let mut result = Vec::new();
+    for v in 0 .. 2 {
+        for u in 0 .. 2 {
+            if !cells[u][v] { continue }
+            let mut max = Vector2 { x: u + 1, y: v + 1 };
+            if max.x < 2 && cells[max.x][v] { max.x += 1; }
+            if max.y < 2 && (u .. max.x).all(|u| cells[u][max.y]) { max.y += 1; }
+            for row in &mut cells[u .. max.x] { for cell in &mut row[v .. max.y] { *cell = false; } }
+            result.push((Vector2 { x: u, y: v }, max));
+        }
+    }
+    result
+}
+
+// The faces of a slope facing north in the bottom half, rising from the bottom of the south side to the top of the north side.
+fn slope_faces() -> Vec<ShapeFace> {
+    let point = |x: f32, y: f32, z: f32| Vector3 { x, y, z };
+    vec![
+        ShapeFace::rectangle(Direction::Below, 0.0, Vector2::from_value(0.0), Vector2::from_value(1.0), 1),
+        ShapeFace::rectangle(Direction::North, 1.0, Vector2::from_value(0.0), Vector2::from_value(1.0), 2),
+        ShapeFace::new(vec![point(1.0, 0.0, 0.0), point(1.0, 0.0, 1.0), point(1.0, 1.0, 1.0)], Vector3::unit_x(), 2, Some(Direction::East)),
+        ShapeFace::new(vec![point(0.0, 0.0, 0.0), point(0.0, 0.0, 1.0), point(0.0, 1.0, 1.0)], -Vector3::unit_x(), 2, Some(Direction::West)),
+        ShapeFace::new(
+            vec![point(0.0, 0.0, 0.0), point(1.0, 0.0, 0.0), point(1.0, 1.0, 1.0), point(0.0, 1.0, 1.0)],
+            Vector3 { x: 0.0, y: 1.0, z: -1.0 }.normalize(), 0, None
+        )
+    ]
+}
+
+// Flips faces upside down in the block space, swapping the above and below tiles.
+fn flip_faces(faces: &mut [ShapeFace]) {
+    for face in faces.iter_mut() {
+        for corner in face.corners.iter_mut() {
+            corner.position[1] = 1.0 - corner.position[1];
+            corner.normal[1] = -corner.normal[1];
+        }
+        // mirroring turns the corners clockwise, so turn them back
+        face.corners.reverse();
+        face.tile = match face.tile { 0 => 1, 1 => 0, other => other };
+        face.side = face.side.map(|side| if side.axis() == Axis::Y { side.opposite() } else { side });
+    }
+}
+
+// Gets the point on the plane at the given depth along the given axis, at the given coordinates across the plane.
+fn plane_point(axis: Axis, depth: f32, u: f32, v: f32) -> Vector3<f32> {
+    match axis {
+        Axis::X => Vector3 { x: depth, y: v, z: u },
+        Axis::Y => Vector3 { x: u, y: depth, z: v },
+        Axis::Z => Vector3 { x: u, y: v, z: depth }
+    }
+}
+
+// Gets the coordinates of a point across the plane of the given axis, the reverse of `plane_point`.
+fn plane_coords(axis: Axis, position: Vector3<f32>) -> Vector2<f32> {
+    match axis {
+        Axis::X => Vector2 { x: position.z, y: position.y },
+        Axis::Y => Vector2 { x: position.x, y: position.z },
+        Axis::Z => Vector2 { x: position.x, y: position.y }
+    }
+}
+
+// Sets the bit of every cell of the 4x4 grid whose corners, moved into the cell by the given fraction of a cell, pass the given test.
+fn cells_where(test: impl Fn([Vector2<f32>; 4]) -> bool, inset: f32) -> u16 {
+    let mut cells = 0;
+    for u in 0 .. 4 {
+        for v in 0 .. 4 {
+            let min = Vector2 { x: u as f32 + inset, y: v as f32 + inset } * 0.25;
+            let max = Vector2 { x: (u + 1) as f32 - inset, y: (v + 1) as f32 - inset } * 0.25;
+            let corners = [min, Vector2 { x: max.x, y: min.y }, max, Vector2 { x: min.x, y: max.y }];
+            if test(corners) { cells |= 1 << (u * 4 + v); }
+        }
+    }
+    cells
+}
+
+// Is the given point inside or on the edge of the given convex polygon?
+fn contains(polygon: &[Vector2<f32>], point: Vector2<f32>) -> bool {
+    let edge = |index: usize| {
+        let (a, b) = (polygon[index], polygon[(index + 1) % polygon.len()]);
+        (b - a).perp_dot(point - a)
+    };
+    (0 .. polygon.len()).all(|index| edge(index) >= -0.0001) || (0 .. polygon.len()).all(|index| edge(index) <= 0.0001)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::states::{BlockProperty, PropertyValue};
+
+    const PROPERTIES: &[BlockProperty] = &[BlockProperty::HorizontalFacing, BlockProperty::Half];
+
+    fn state(facing: Direction, half: Half) -> BlockState {
+        BlockState::default(PROPERTIES).with("facing", PropertyValue::Direction(facing)).with("half", PropertyValue::Half(half))
+    }
+
+    // finds the corner of bottom stairs facing north with the given stairs around it
+    fn corner(neighbours: &[(Direction, BlockState)]) -> StairsCorner {
+        StairsCorner::find(state(Direction::North, Half::Bottom), |direction| neighbours.iter().find(|(side, _)| *side == direction).map(|(_, state)| *state))
+    }
+
+    #[test]
+    fn stairs_bend_towards_stairs_across_them() {
+        assert_eq!(corner(&[]), StairsCorner::Straight);
+        assert_eq!(corner(&[(Direction::North, state(Direction::East, Half::Bottom))]), StairsCorner::Outer(Direction::East));
+        assert_eq!(corner(&[(Direction::South, state(Direction::West, Half::Bottom))]), StairsCorner::Inner(Direction::West));
+
+        // stairs of the other half or facing along the same axis do not join
+        assert_eq!(corner(&[(Direction::North, state(Direction::East, Half::Top))]), StairsCorner::Straight);
+        assert_eq!(corner(&[(Direction::South, state(Direction::South, Half::Bottom))]), StairsCorner::Straight);
+    }
+
+    #[test]
+    fn straight_runs_of_stairs_do_not_bend() {
+        // the stairs behind face east, but the stairs to the west continue the run facing north
+        let run = corner(&[(Direction::North, state(Direction::East, Half::Bottom)), (Direction::West, state(Direction::North, Half::Bottom))]);
+        assert_eq!(run, StairsCorner::Straight);
+
+        // the same for the stairs in front, which would bend towards the east
+        let run = corner(&[(Direction::South, state(Direction::East, Half::Bottom)), (Direction::East, state(Direction::North, Half::Bottom))]);
+        assert_eq!(run, StairsCorner::Straight);
+    }
+
+    // gets the cover of the face of a slope on the given side
+    fn slope_face(state: BlockState, side: Direction) -> SideCover {
+        shape_faces(BlockShape::Slope, state, StairsCorner::Straight).iter().find(|face| face.side == Some(side)).unwrap().cover()
+    }
+
+    #[test]
+    fn slopes_side_by_side_hide_each_others_triangles() {
+        let slope = state(Direction::North, Half::Bottom);
+        let east = slope_face(slope, Direction::East);
+        assert_ne!(east.triangle, 0);
+
+        // the slope to the east covers its west side with the same triangle
+        assert!(side_cover(BlockShape::Slope, slope, Direction::West).hides(east));
+        assert!(side_cover(BlockShape::Slope, slope, Direction::East).hides(slope_face(slope, Direction::West)));
+
+        // a slope facing the other way or flipped upside down has a different triangle
+        assert!(!side_cover(BlockShape::Slope, state(Direction::South, Half::Bottom), Direction::West).hides(east));
+        assert!(!side_cover(BlockShape::Slope, state(Direction::North, Half::Top), Direction::West).hides(east));
+    }
+
+    #[test]
+    fn full_faces_next_to_slopes_stay_visible() {
+        let slope = state(Direction::North, Half::Bottom);
+        assert!(!side_cover(BlockShape::Slope, slope, Direction::East).hides(SideCover::FULL));
+
+        // the full back of the slope does hide a full face
+        assert!(side_cover(BlockShape::Slope, slope, Direction::North).hides(SideCover::FULL));
+    }
+}
@@ -0,0 +1,263 @@
+use cgmath::*;
+use forte_engine::math::transforms::Transform;
+
+use crate::terrain::{blocks::*, vertices::ChunkVertex};
+
+/// How a `Chunk` turns its blocks into a mesh.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ChunkMesher {
+    /// Each block is drawn by its `BlockRenderer`.
+    #[default]
+    Blocks,
+    /// The data of each block is a density, and a smooth surface is drawn between dense and empty blocks with `surface_nets`.
+    /// Each part of the surface is textured with the tiles of the nearby material from `BlockRenderer::lod_tiles`, projected along all three axes.  Smooth chunks are always meshed at full detail.
+    Smooth
+}
+
+/// The density at which the smooth surface is drawn.  Blocks denser than this are inside the surface.
+pub const ISO_LEVEL: f32 = 0.5;
+
+/// Gets the density of a block for smooth meshing, which is its data scaled from 0 to 1.  Air (material 0) always has a density of 0.
+pub fn density(material: u16, data: u16) -> f32 {
+    if material == 0 { 0.0 } else { data as f32 / u16::MAX as f32 }
+}
+
+/// Gets the data that gives a block the given density for smooth meshing, see `density`.
+pub fn density_data(density: f32) -> u16 { (density.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16 }
+
+/// Extracts a smooth surface from a box of block densities using surface nets.
+///
+/// Densities are sampled at the center of each block, and one vertex is placed in each cell of 8 samples that the surface passes through.
+/// Only surface between samples starting inside the box is drawn, so boxes next to each other draw their shared surface once, with matching vertices at their borders if `sample` gives the blocks of the neighbouring boxes.
+///
+/// Arguments:
+/// * definitions: &[BlockDef<M>] - The definitions of the materials in the box.
+/// * size: Vector3<usize> - The size of the box in blocks.
+/// * sample: impl Fn(Vector3<i32>) -> (u16, u16) - Gets the material and data of the block at the given location, from -1 to `size` on each axis.
+/// * transform: &Transform - The transform of the box, used for tints.
+/// * resources: RenderResources - The resources used to resolve tiles and tints.
+///
+/// Returns the vertices of the surface, positioned in blocks.
+pub fn surface_nets<M: MaterialDef + 'static>(
+    definitions: &[BlockDef<M>],
+    size: Vector3<usize>,
+    sample: impl Fn(Vector3<i32>) -> (u16, u16),
+    transform: &Transform,
+    resources: RenderResources
+) -> Vec<ChunkVertex> {
+    let mut vertices = Vec::new();
+    surface_quads(definitions, size, sample, transform, resources, |_, quad| vertices.extend(quad));
+    vertices
+}
+
+/// Extracts a smooth surface like `surface_nets`, with its vertices grouped by material in the order materials are first found.  Each part of the surface belongs to the material of the dense block it covers.
+///
+/// Returns the material and vertices of each group, positioned in blocks.
+pub fn surface_nets_grouped<M: MaterialDef + 'static>(
+    definitions: &[BlockDef<M>],
+    size: Vector3<usize>,
+    sample: impl Fn(Vector3<i32>) -> (u16, u16),
+    transform: &Transform,
+    resources: RenderResources
+) -> Vec<(u16, Vec<ChunkVertex>)> {
+    let mut groups: Vec<(u16, Vec<ChunkVertex>)> = Vec::new();
+    surface_quads(definitions, size, sample, transform, resources, |material, quad| {
+        match groups.iter_mut().find(|(group, _)| *group == material) {
+            Some((_, group)) => group.extend(quad),
+            None => groups.push((material, quad.to_vec()))
+        }
+    });
+    groups
+}
+
+// Extracts the surface of a box, giving each quad of 2 triangles to `output` with the material of the dense block it covers.
+fn surface_quads<M: MaterialDef + 'static>(
+    definitions: &[BlockDef<M>],
+    size: Vector3<usize>,
+    sample: impl Fn(Vector3<i32>) -> (u16, u16),
+    transform: &Transform,
+    resources: RenderResources,
+    mut output: impl FnMut(u16, [ChunkVertex; 6])
+) {
+    // sample the box with a border of one block, so samples from -1 to size
+    let samples_size = size.cast::<i32>().unwrap() + Vector3::from_value(2);
+    let sample_index = |location: Vector3<i32>| (((location.x + 1) * samples_size.y + location.y + 1) * samples_size.z + location.z + 1) as usize;
+    let mut samples = Vec::with_capacity((samples_size.x * samples_size.y * samples_size.z) as usize);
+    for x in -1 .. samples_size.x - 1 {
+        for y in -1 .. samples_size.y - 1 {
+            for z in -1 .. samples_size.z - 1 {
+                let (material, data) = sample(Vector3 { x, y, z });
+                samples.push((material, density(material, data)));
+            }
+        }
+    }
+
+    // place a vertex in every cell the surface passes through, where cells are named by their minimum sample from -1 to size - 1
+    let cells_size = samples_size - Vector3::from_value(1);
+    let cell_index = |location: Vector3<i32>| (((location.x + 1) * cells_size.y + location.y + 1) * cells_size.z + location.z + 1) as usize;
+    let mut cells: Vec<Option<ChunkVertex>> = Vec::with_capacity((cells_size.x * cells_size.y * cells_size.z) as usize);
+    for x in -1 .. cells_size.x - 1 {
+        for y in -1 .. cells_size.y - 1 {
+            for z in -1 .. cells_size.z - 1 {
+                let corners = CORNERS.map(|corner| samples[sample_index(Vector3 { x, y, z } + corner)]);
+                cells.push(cell_vertex(definitions, &corners, Vector3 { x, y, z }, transform, resources));
+            }
+        }
+    }
+
+    // join the vertices of the 4 cells around each edge between samples that crosses the surface
+    for axis in 0 .. 3 {
+        let (b, c) = ((axis + 1) % 3, (axis + 2) % 3);
+        let (step, step_b, step_c) = (unit(axis), unit(b), unit(c));
+        for x in 0 .. size.x as i32 {
+            for y in 0 .. size.y as i32 {
+                for z in 0 .. size.z as i32 {
+                    let start = Vector3 { x, y, z };
+                    let inside = samples[sample_index(start)].1 > ISO_LEVEL;
+                    if inside == (samples[sample_index(start + step)].1 > ISO_LEVEL) { continue }
+
+                    let quad = [start - step_b - step_c, start - step_c, start, start - step_b].map(|cell| cells[cell_index(cell)]);
+                    let [a, b, c, d] = match quad { [Some(a), Some(b), Some(c), Some(d)] => [a, b, c, d], _ => continue };
+
+                    // the quad is counter clockwise around the axis, so flip it when the surface faces the other way
+                    if inside { output(samples[sample_index(start)].0, [a, b, c, a, c, d]); }
+                    else { output(samples[sample_index(start + step)].0, [a, c, b, a, d, c]); }
+                }
+            }
+        }
+    }
+}
+
+const CORNERS: [Vector3<i32>; 8] = [
+    Vector3 { x: 0, y: 0, z: 0 }, Vector3 { x: 0, y: 0, z: 1 }, Vector3 { x: 0, y: 1, z: 0 }, Vector3 { x: 0, y: 1, z: 1 },
+    Vector3 { x: 1, y: 0, z: 0 }, Vector3 { x: 1, y: 0, z: 1 }, Vector3 { x: 1, y: 1, z: 0 }, Vector3 { x: 1, y: 1, z: 1 }
+];
+
+// the pairs of corners joined by the 12 edges of a cell
+const EDGES: [(usize, usize); 12] = [(0, 1), (2, 3), (4, 5), (6, 7), (0, 2), (1, 3), (4, 6), (5, 7), (0, 4), (1, 5), (2, 6), (3, 7)];
+
+fn unit(axis: usize) -> Vector3<i32> { Vector3 { x: (axis == 0) as i32, y: (axis == 1) as i32, z: (axis == 2) as i32 } }
+
+// Creates the vertex of a cell at the average of the points where its edges cross the surface, or `None` if the surface does not pass through it.
+fn cell_vertex<M: MaterialDef + 'static>(definitions: &[BlockDef<M>], corners: &[(u16, f32); 8], cell: Vector3<i32>, transform: &Transform, resources: RenderResources) -> Option<ChunkVertex> {
+    let mut sum = Vector3::zero();
+    let mut count = 0;
+    for (a, b) in EDGES {
+        let (density_a, density_b) = (corners[a].1, corners[b].1);
+        if (density_a > ISO_LEVEL) == (density_b > ISO_LEVEL) { continue }
+        let t = (ISO_LEVEL - density_a) / (density_b - density_a);
+        sum += CORNERS[a].cast::<f32>().unwrap().lerp(CORNERS[b].cast::<f32>().unwrap(), t);
+        count += 1;
+    }
+    if count == 0 { return None }
+
+    // the normal points away from where the density increases
+    let mut gradient = Vector3::zero();
+    for (index, corner) in CORNERS.iter().enumerate() {
+        let sign = corner.cast::<f32>().unwrap() * 2.0 - Vector3::from_value(1.0);
+        gradient += sign * corners[index].1;
+    }
+    let normal = if gradient.magnitude2() > 0.0 { -gradient.normalize() } else { Vector3::unit_y() };
+
+    // texture and tint with the densest material that has tiles, samples are at the center of blocks
+    let definition = corners.iter()
+        .filter(|(material, density)| *density > ISO_LEVEL && definitions[*material as usize].renderer.lod_tiles().is_some())
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(material, _)| &definitions[*material as usize]);
+    let (layer, animation) = definition.and_then(|definition| tiles_layer(definition, normal, resources)).unwrap_or((0, 0));
+    let position = cell.cast::<f32>().unwrap() + sum / count as f32 + Vector3::from_value(0.5);
+    let mut vertex = vec![ChunkVertex { position: position.into(), tex_coords: [0.0, 0.0], normal: normal.into(), layer, animation, color: [1.0, 1.0, 1.0], triplanar: 1 }];
+    if let Some(definition) = definition { definition.tint_vertices(&mut vertex, Matrix3::identity(), transform, resources); }
+    Some(vertex[0])
+}
+
+// Gets the atlas layer and animation to texture a smooth surface of the given material with, using its above tile for surfaces facing up and its north tile otherwise.
+fn tiles_layer<M: MaterialDef + 'static>(definition: &BlockDef<M>, normal: Vector3<f32>, resources: RenderResources) -> Option<(u32, u32)> {
+    let tiles = definition.renderer.lod_tiles()?;
+    let tile = if normal.y > 0.7 { &tiles[0] } else { &tiles[2] };
+    let (layer, animation) = resources.resolve(tile);
+    Some((layer as u32, animation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{define_blocks_materials, terrain::{animations::TileAnimations, atlas::{Tile, TileNames}, block_models::BlockModels}};
+
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        AIR => { transparent: true, renderer: BlockRenderer::None },
+        STONE => { transparent: false, renderer: BlockRenderer::Standard(Tile::Index(1), Tile::Index(1), Tile::Index(1), Tile::Index(1), Tile::Index(1), Tile::Index(1)) },
+        DIRT => { transparent: false, renderer: BlockRenderer::Standard(Tile::Index(2), Tile::Index(2), Tile::Index(2), Tile::Index(2), Tile::Index(2), Tile::Index(2)) }
+    ]);
+
+    // a full block of stone below a full block of dirt in a box of air
+    fn sample(location: Vector3<i32>) -> (u16, u16) {
+        match (location.x, location.y, location.z) {
+            (1, 1, 1) => (Material::STONE as u16, u16::MAX),
+            (1, 2, 1) => (Material::DIRT as u16, u16::MAX),
+            _ => (Material::AIR as u16, 0)
+        }
+    }
+
+    #[test]
+    fn grouped_surfaces_match_the_whole_surface() {
+        let (tiles, animations, models) = (TileNames::new(), TileAnimations::new(), BlockModels::new());
+        let resources = RenderResources { tiles: &tiles, animations: &animations, models: &models, tints: None };
+        let size = Vector3::new(4, 4, 4);
+        let vertices = surface_nets(Blocks::DEFINITIONS, size, sample, &Transform::default(), resources);
+        let groups = surface_nets_grouped(Blocks::DEFINITIONS, size, sample, &Transform::default(), resources);
+
+        assert!(!vertices.is_empty());
+        assert_eq!(groups.iter().map(|(material, _)| *material).collect::<Vec<u16>>(), vec![Material::STONE as u16, Material::DIRT as u16]);
+        assert_eq!(groups.iter().map(|(_, vertices)| vertices.len()).sum::<usize>(), vertices.len());
+        assert!(vertices.iter().all(|vertex| vertex.triplanar == 1));
+
+        // stone covers the bottom of the surface and dirt the top
+        let height = |vertices: &[ChunkVertex]| vertices.iter().map(|vertex| vertex.position[1]).sum::<f32>() / vertices.len() as f32;
+        assert!(height(&groups[0].1) < 2.0 && height(&groups[1].1) > 2.0);
+    }
+
+    // a ball of stone centered on the border between two 4x4x4 boxes side by side along X
+    fn ball(location: Vector3<i32>) -> (u16, u16) {
+        let distance = (location.cast::<f32>().unwrap() - Vector3::new(4.0, 2.0, 2.0)).magnitude();
+        let density = 0.5 + (2.5 - distance) / 2.0;
+        if density > 0.0 { (Material::STONE as u16, density_data(density)) } else { (Material::AIR as u16, 0) }
+    }
+
+    #[test]
+    fn neighbouring_boxes_share_their_border_surface() {
+        let (tiles, animations, models) = (TileNames::new(), TileAnimations::new(), BlockModels::new());
+        let resources = RenderResources { tiles: &tiles, animations: &animations, models: &models, tints: None };
+        let size = Vector3::new(4, 4, 4);
+        let offset = Vector3::new(4, 0, 0);
+
+        // each box samples its border from the other, and the second box is moved into place
+        let first = surface_nets(Blocks::DEFINITIONS, size, ball, &Transform::default(), resources);
+        let mut second = surface_nets(Blocks::DEFINITIONS, size, |location| ball(location + offset), &Transform::default(), resources);
+        second.iter_mut().for_each(|vertex| vertex.position[0] += offset.x as f32);
+        let whole = surface_nets(Blocks::DEFINITIONS, Vector3::new(8, 4, 4), ball, &Transform::default(), resources);
+
+        // positions rounded so the same point found from either box compares equal
+        let key = |vertex: &ChunkVertex| vertex.position.map(|coord| (coord * 1000.0).round() as i32);
+        let sorted = |vertices: &[ChunkVertex]| {
+            let mut triangles: Vec<[[i32; 3]; 3]> = vertices.chunks(3).map(|triangle| [key(&triangle[0]), key(&triangle[1]), key(&triangle[2])]).collect();
+            triangles.sort();
+            triangles
+        };
+
+        // together the boxes draw exactly the surface of one box covering both, so nothing on the border is drawn twice or left out
+        assert!(!first.is_empty() && !second.is_empty());
+        assert_eq!(first.len() + second.len(), whole.len());
+        assert_eq!(sorted(&[first.clone(), second.clone()].concat()), sorted(&whole));
+
+        // both boxes place the same vertices in the cells on the border
+        let on_border = |vertices: &[ChunkVertex]| {
+            let mut positions: Vec<[i32; 3]> = vertices.iter().filter(|vertex| (3.5 ..= 4.5).contains(&vertex.position[0])).map(key).collect();
+            positions.sort();
+            positions.dedup();
+            positions
+        };
+        assert!(!on_border(&first).is_empty());
+        assert_eq!(on_border(&first), on_border(&second));
+    }
+}
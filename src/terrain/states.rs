@@ -0,0 +1,341 @@
+use cgmath::*;
+use serde::*;
+
+/// A direction a block can face.  These match the above, below, north, south, east and west naming used by `BlockRenderer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction { Above, Below, North, South, East, West }
+
+impl Direction {
+    /// All directions in the order above, below, north, south, east, west.
+    pub const ALL: [Direction; 6] = [Direction::Above, Direction::Below, Direction::North, Direction::South, Direction::East, Direction::West];
+    /// All horizontal directions in the order north, south, east, west.
+    pub const HORIZONTAL: [Direction; 4] = [Direction::North, Direction::South, Direction::East, Direction::West];
+
+    /// Returns the index of this direction in `Direction::ALL`.
+    pub fn index(&self) -> usize { *self as usize }
+
+    /// Returns the direction opposite to this one.
+    pub fn opposite(&self) -> Self {
+        match self {
+            Self::Above => Self::Below,
+            Self::Below => Self::Above,
+            Self::North => Self::South,
+            Self::South => Self::North,
+            Self::East => Self::West,
+            Self::West => Self::East
+        }
+    }
+
+    /// Returns the unit normal of this direction.  North is +Z, east is +X and above is +Y.
+    pub fn normal(&self) -> Vector3<f32> {
+        match self {
+            Self::Above => Vector3::unit_y(),
+            Self::Below => -Vector3::unit_y(),
+            Self::North => Vector3::unit_z(),
+            Self::South => -Vector3::unit_z(),
+            Self::East => Vector3::unit_x(),
+            Self::West => -Vector3::unit_x()
+        }
+    }
+
+    /// Returns the direction closest to the given normal.
+    pub fn from_normal(normal: Vector3<f32>) -> Self {
+        let abs = Vector3 { x: normal.x.abs(), y: normal.y.abs(), z: normal.z.abs() };
+        if abs.y >= abs.x && abs.y >= abs.z { if normal.y > 0.0 { Self::Above } else { Self::Below } }
+        else if abs.z >= abs.x { if normal.z > 0.0 { Self::North } else { Self::South } }
+        else if normal.x > 0.0 { Self::East } else { Self::West }
+    }
+
+    /// Returns the axis this direction lies on.
+    pub fn axis(&self) -> Axis {
+        match self {
+            Self::Above | Self::Below => Axis::Y,
+            Self::North | Self::South => Axis::Z,
+            Self::East | Self::West => Axis::X
+        }
+    }
+
+    /// Returns this direction turned the given number of quarter turns around the Y axis, where one turn takes north to east.
+    pub fn rotate_y(&self, turns: u32) -> Self {
+        (0 .. turns % 4).fold(*self, |direction, _| match direction {
+            Self::North => Self::East,
+            Self::East => Self::South,
+            Self::South => Self::West,
+            Self::West => Self::North,
+            other => other
+        })
+    }
+
+    /// Returns this direction mirrored across the plane perpendicular to the given axis.
+    pub fn mirror(&self, axis: Axis) -> Self { if self.axis() == axis { self.opposite() } else { *self } }
+}
+
+/// An axis a block can be aligned to, like a log or pillar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis { X, Y, Z }
+
+/// The half of a block space that a block occupies, like a slab or stair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Half { Top, Bottom }
+
+/// A property that can be declared on a block to give meaning to its data slot.
+///
+/// Properties are packed into the blocks `u16` data slot in the order they are declared, starting at the lowest bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockProperty {
+    /// The direction the block faces in all 6 directions, named "facing".  Uses 3 bits.
+    Facing,
+    /// The direction the block faces horizontally, named "facing".  Uses 2 bits.
+    HorizontalFacing,
+    /// The axis the block is aligned to, named "axis".  Uses 2 bits.
+    Axis,
+    /// The half of the block space that is occupied, named "half".  Uses 1 bit.
+    Half,
+    /// If the block is filled with water, named "waterlogged".  Uses 1 bit.
+    Waterlogged,
+    /// A custom boolean with the given name.  Uses 1 bit.
+    Bool(&'static str),
+    /// A custom integer with the given name that can be from 0 to the given maximum inclusive.
+    Int(&'static str, u16)
+}
+
+impl BlockProperty {
+    /// The name of this property.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Facing | Self::HorizontalFacing => "facing",
+            Self::Axis => "axis",
+            Self::Half => "half",
+            Self::Waterlogged => "waterlogged",
+            Self::Bool(name) | Self::Int(name, _) => name
+        }
+    }
+
+    /// The number of bits this property uses in the data slot.
+    pub const fn bits(&self) -> u32 {
+        match self {
+            Self::Facing => 3,
+            Self::HorizontalFacing | Self::Axis => 2,
+            Self::Half | Self::Waterlogged | Self::Bool(_) => 1,
+            Self::Int(_, max) => u16::BITS - max.leading_zeros()
+        }
+    }
+
+    /// The total number of bits the given properties use in the data slot.  This must be at most 16 for the properties to fit in a blocks data slot.
+    pub const fn total_bits(properties: &[BlockProperty]) -> u32 {
+        let mut total = 0;
+        let mut index = 0;
+        while index < properties.len() {
+            total += properties[index].bits();
+            index += 1;
+        }
+        total
+    }
+
+    fn decode(&self, raw: u16) -> PropertyValue {
+        match self {
+            Self::Facing => PropertyValue::Direction(Direction::ALL[(raw as usize).min(5)]),
+            Self::HorizontalFacing => PropertyValue::Direction(Direction::HORIZONTAL[raw as usize]),
+            Self::Axis => PropertyValue::Axis([Axis::X, Axis::Y, Axis::Z][(raw as usize).min(2)]),
+            Self::Half => PropertyValue::Half(if raw == 0 { Half::Bottom } else { Half::Top }),
+            Self::Waterlogged | Self::Bool(_) => PropertyValue::Bool(raw != 0),
+            Self::Int(_, max) => PropertyValue::Int(raw.min(*max))
+        }
+    }
+
+    fn encode(&self, value: PropertyValue) -> Option<u16> {
+        match (self, value) {
+            (Self::Facing, PropertyValue::Direction(direction)) => Direction::ALL.iter().position(|d| *d == direction).map(|i| i as u16),
+            (Self::HorizontalFacing, PropertyValue::Direction(direction)) => Direction::HORIZONTAL.iter().position(|d| *d == direction).map(|i| i as u16),
+            (Self::Axis, PropertyValue::Axis(axis)) => Some(axis as u16),
+            (Self::Half, PropertyValue::Half(half)) => Some(if half == Half::Top { 1 } else { 0 }),
+            (Self::Waterlogged | Self::Bool(_), PropertyValue::Bool(value)) => Some(value as u16),
+            (Self::Int(_, max), PropertyValue::Int(value)) => if value <= *max { Some(value) } else { None },
+            _ => None
+        }
+    }
+}
+
+/// A typed value of a `BlockProperty`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyValue {
+    Direction(Direction),
+    Axis(Axis),
+    Half(Half),
+    Bool(bool),
+    Int(u16)
+}
+
+/// A view of a blocks data slot through the `BlockProperty`s declared for that block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockState {
+    /// The properties declared for the block.
+    pub properties: &'static [BlockProperty],
+    /// The raw data slot of the block.
+    pub data: u16
+}
+
+impl BlockState {
+    /// Creates a new block state from a blocks properties and its raw data.
+    ///
+    /// The properties must fit in the 16 bits of the data slot, which `define_blocks_materials!` checks when it is compiled.
+    pub fn new(properties: &'static [BlockProperty], data: u16) -> Self {
+        debug_assert!(BlockProperty::total_bits(properties) <= u16::BITS, "Block properties {:?} use more than {} bits!", properties, u16::BITS);
+        Self { properties, data }
+    }
+
+    /// Creates a block state with all properties set to their first value.
+    pub fn default(properties: &'static [BlockProperty]) -> Self { Self::new(properties, 0) }
+
+    // find the property with the given name and its bit offset
+    fn find(&self, name: &str) -> Option<(&'static BlockProperty, u32)> {
+        let mut offset = 0;
+        for property in self.properties {
+            if property.name() == name { return Some((property, offset)) }
+            offset += property.bits();
+        }
+        None
+    }
+
+    /// Gets the value of the property with the given name, or `None` if the block does not have that property.
+    pub fn get(&self, name: &str) -> Option<PropertyValue> {
+        let (property, offset) = self.find(name)?;
+        let mask = (1u32 << property.bits()) - 1;
+        Some(property.decode(((self.data as u32 >> offset) & mask) as u16))
+    }
+
+    /// Returns a copy of this state with the property with the given name set to the given value.
+    ///
+    /// Panics if the block does not have the property or the value does not fit the property.
+    pub fn with(&self, name: &str, value: PropertyValue) -> Self {
+        let (property, offset) = self.find(name).unwrap_or_else(|| panic!("Block does not have property {}!", name));
+        let raw = property.encode(value).unwrap_or_else(|| panic!("Value {:?} is not valid for property {}!", value, name));
+        let mask = ((1u32 << property.bits()) - 1) << offset;
+        let data = (self.data as u32 & !mask) | ((raw as u32) << offset);
+        Self { properties: self.properties, data: data as u16 }
+    }
+
+    /// The direction this block is facing, if it has a "facing" property.
+    pub fn facing(&self) -> Option<Direction> {
+        match self.get("facing") { Some(PropertyValue::Direction(direction)) => Some(direction), _ => None }
+    }
+
+    /// The axis this block is aligned to, if it has an "axis" property.
+    pub fn axis(&self) -> Option<Axis> {
+        match self.get("axis") { Some(PropertyValue::Axis(axis)) => Some(axis), _ => None }
+    }
+
+    /// The half this block occupies, if it has a "half" property.
+    pub fn half(&self) -> Option<Half> {
+        match self.get("half") { Some(PropertyValue::Half(half)) => Some(half), _ => None }
+    }
+
+    /// Is this block waterlogged?  Blocks without a "waterlogged" property never are.
+    pub fn waterlogged(&self) -> bool {
+        matches!(self.get("waterlogged"), Some(PropertyValue::Bool(true)))
+    }
+
+    /// The rotation from the default orientation of this block, facing north and aligned to the Y axis, to its orientation in the world.
+    /// The "facing" property is used if present, otherwise the "axis" property, otherwise no rotation is applied.
+    pub fn rotation(&self) -> Matrix3<f32> {
+        if let Some(facing) = self.facing() {
+            match facing {
+                Direction::North => Matrix3::identity(),
+                Direction::South => Matrix3::from_angle_y(Deg(180.0)),
+                Direction::East => Matrix3::from_angle_y(Deg(90.0)),
+                Direction::West => Matrix3::from_angle_y(Deg(-90.0)),
+                Direction::Above => Matrix3::from_angle_x(Deg(-90.0)),
+                Direction::Below => Matrix3::from_angle_x(Deg(90.0))
+            }
+        } else if let Some(axis) = self.axis() {
+            match axis {
+                Axis::X => Matrix3::from_angle_z(Deg(-90.0)),
+                Axis::Y => Matrix3::identity(),
+                Axis::Z => Matrix3::from_angle_x(Deg(90.0))
+            }
+        } else { Matrix3::identity() }
+    }
+
+    /// Returns a copy of this state turned the given number of quarter turns around the Y axis, where one turn takes north to east.  The "facing" and "axis" properties are turned, others are left alone.
+    pub fn rotate_y(&self, turns: u32) -> Self {
+        let mut state = *self;
+        if let Some(facing) = self.facing() { state = state.with("facing", PropertyValue::Direction(facing.rotate_y(turns))); }
+        if let Some(axis) = self.axis() {
+            let axis = match axis { Axis::X if turns % 2 == 1 => Axis::Z, Axis::Z if turns % 2 == 1 => Axis::X, other => other };
+            state = state.with("axis", PropertyValue::Axis(axis));
+        }
+        state
+    }
+
+    /// Returns a copy of this state mirrored across the plane perpendicular to the given axis.  Only the "facing" property is changed.
+    pub fn mirror(&self, axis: Axis) -> Self {
+        match self.facing() {
+            Some(facing) => self.with("facing", PropertyValue::Direction(facing.mirror(axis))),
+            None => *self
+        }
+    }
+
+    /// Gets the value of a custom boolean property.
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get(name) { Some(PropertyValue::Bool(value)) => Some(value), _ => None }
+    }
+
+    /// Gets the value of a custom integer property.
+    pub fn get_int(&self, name: &str) -> Option<u16> {
+        match self.get(name) { Some(PropertyValue::Int(value)) => Some(value), _ => None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROPERTIES: &[BlockProperty] = &[BlockProperty::HorizontalFacing, BlockProperty::Half, BlockProperty::Int("age", 5), BlockProperty::Bool("lit")];
+
+    #[test]
+    fn properties_pack_in_order() {
+        assert_eq!(BlockProperty::total_bits(PROPERTIES), 2 + 1 + 3 + 1);
+        let state = BlockState::default(PROPERTIES)
+            .with("facing", PropertyValue::Direction(Direction::West))
+            .with("half", PropertyValue::Half(Half::Top))
+            .with("age", PropertyValue::Int(5))
+            .with("lit", PropertyValue::Bool(true));
+        assert_eq!(state.data, 3 | 1 << 2 | 5 << 3 | 1 << 6);
+        assert_eq!(state.facing(), Some(Direction::West));
+        assert_eq!(state.half(), Some(Half::Top));
+        assert_eq!(state.get_int("age"), Some(5));
+        assert_eq!(state.get_bool("lit"), Some(true));
+        assert_eq!(state.axis(), None);
+    }
+
+    #[test]
+    fn setting_a_property_leaves_the_others() {
+        let state = BlockState::new(PROPERTIES, 0b1111111).with("age", PropertyValue::Int(2));
+        assert_eq!(state.facing(), Some(Direction::West));
+        assert_eq!(state.half(), Some(Half::Top));
+        assert_eq!(state.get_int("age"), Some(2));
+        assert_eq!(state.get_bool("lit"), Some(true));
+    }
+
+    #[test]
+    #[should_panic]
+    fn values_must_fit_their_property() {
+        BlockState::default(PROPERTIES).with("age", PropertyValue::Int(6));
+    }
+
+    #[test]
+    fn rotating_and_mirroring_turn_facing_and_axis() {
+        let facing = BlockState::default(PROPERTIES);
+        assert_eq!(facing.rotate_y(1).facing(), Some(Direction::East));
+        assert_eq!(facing.rotate_y(6).facing(), Some(Direction::South));
+        assert_eq!(facing.mirror(Axis::Z).facing(), Some(Direction::South));
+        assert_eq!(facing.mirror(Axis::X).facing(), Some(Direction::North));
+
+        const AXIS: &[BlockProperty] = &[BlockProperty::Axis];
+        let axis = BlockState::default(AXIS).with("axis", PropertyValue::Axis(Axis::X));
+        assert_eq!(axis.rotate_y(1).axis(), Some(Axis::Z));
+        assert_eq!(axis.rotate_y(2).axis(), Some(Axis::X));
+    }
+}
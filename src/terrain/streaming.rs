@@ -0,0 +1,287 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::*;
+use forte_engine::render::render_engine::RenderEngine;
+
+use crate::terrain::{blocks::*, chunk::{Chunk, CHUNK_SIZE}, edits::BlockWorld, smooth::ChunkMesher};
+
+/// Provides the chunks loaded by a `ChunkStreamer`, either by generating them or by reading them from storage.
+///
+/// This is implemented for any `FnMut(Vector3<i32>) -> Chunk<T, M, X, Y, Z>` which generates the chunk at the given grid position and never saves.
+pub trait ChunkProvider<T: BlockDefinitions<M>, M: MaterialDef + 'static, const X: usize = CHUNK_SIZE, const Y: usize = CHUNK_SIZE, const Z: usize = CHUNK_SIZE> {
+    /// Loads or generates the chunk at the given grid position.  The streamer sets the id and position of the returned chunk.
+    fn load(&mut self, position: Vector3<i32>) -> Chunk<T, M, X, Y, Z>;
+
+    /// Saves a chunk that is being unloaded.  This is only called if the streamer has saving enabled, and does nothing by default.
+    fn save(&mut self, _position: Vector3<i32>, _chunk: &Chunk<T, M, X, Y, Z>) {}
+}
+
+impl <T: BlockDefinitions<M>, M: MaterialDef + 'static, const X: usize, const Y: usize, const Z: usize, F: FnMut(Vector3<i32>) -> Chunk<T, M, X, Y, Z>> ChunkProvider<T, M, X, Y, Z> for F {
+    fn load(&mut self, position: Vector3<i32>) -> Chunk<T, M, X, Y, Z> { self(position) }
+}
+
+/// Keeps the chunks around a moving viewer loaded, unloading chunks that get too far away.
+///
+/// Chunks are stored by their grid position, where the chunk at grid position `p` is placed at `p` times the chunk size in the world.
+/// Missing chunks are loaded closest first, and only up to a budget each update so a fast moving viewer does not stall a frame.  The same applies to meshing.
+///
+/// Example
+/// ```rust,ignore
+/// let mut streamer = ChunkStreamer::new(|position: Vector3<i32>| generate(position), 8, 4).with_budget(4, 2);
+///
+/// // each frame
+/// streamer.update(camera.position);
+/// streamer.mesh(&engine, RenderResources::new(&atlas, &models));
+/// streamer.chunks_mut().for_each(|(_, chunk)| chunk.draw(&engine, &mut pass, &atlas));
+/// ```
+pub struct ChunkStreamer<T: BlockDefinitions<M>, M: MaterialDef + 'static, P: ChunkProvider<T, M, X, Y, Z>, const X: usize = CHUNK_SIZE, const Y: usize = CHUNK_SIZE, const Z: usize = CHUNK_SIZE> {
+    provider: P,
+    chunks: HashMap<Vector3<i32>, Chunk<T, M, X, Y, Z>>,
+    center: Vector3<i32>,
+    next_id: u32,
+    pending_borders: HashSet<Vector3<i32>>,
+    /// How many chunks away from the viewer horizontally chunks stay loaded, measured as a circle.
+    pub horizontal_radius: i32,
+    /// How many chunks above or below the viewer chunks stay loaded.
+    pub vertical_radius: i32,
+    /// The most chunks loaded in one call to `update`.
+    pub load_budget: usize,
+    /// The most chunks meshed in one call to `mesh`.
+    pub mesh_budget: usize,
+    /// If true, chunks are passed to `ChunkProvider::save` when they are unloaded.
+    pub save_on_unload: bool
+}
+
+impl <T: BlockDefinitions<M>, M: MaterialDef + 'static, P: ChunkProvider<T, M, X, Y, Z>, const X: usize, const Y: usize, const Z: usize> ChunkStreamer<T, M, P, X, Y, Z> {
+    /// Creates a new streamer with no chunks loaded, a budget of 4 loads and 4 meshes per update, and saving disabled.
+    ///
+    /// Arguments:
+    /// * provider: P - The `ChunkProvider` that loads chunks.
+    /// * horizontal_radius: i32 - How many chunks away from the viewer horizontally chunks stay loaded.
+    /// * vertical_radius: i32 - How many chunks above or below the viewer chunks stay loaded.
+    pub fn new(provider: P, horizontal_radius: i32, vertical_radius: i32) -> Self {
+        Self {
+            provider,
+            chunks: HashMap::new(),
+            center: Vector3::zero(),
+            next_id: 0,
+            pending_borders: HashSet::new(),
+            horizontal_radius,
+            vertical_radius,
+            load_budget: 4,
+            mesh_budget: 4,
+            save_on_unload: false
+        }
+    }
+
+    /// Returns this streamer with the given load and mesh budgets.
+    pub fn with_budget(self, load_budget: usize, mesh_budget: usize) -> Self { Self { load_budget, mesh_budget, ..self } }
+
+    /// Returns this streamer with saving on unload enabled or disabled.
+    pub fn with_saving(self, save_on_unload: bool) -> Self { Self { save_on_unload, ..self } }
+
+    pub fn provider(&self) -> &P { &self.provider }
+    pub fn provider_mut(&mut self) -> &mut P { &mut self.provider }
+    pub fn get(&self, position: Vector3<i32>) -> Option<&Chunk<T, M, X, Y, Z>> { self.chunks.get(&position) }
+    pub fn get_mut(&mut self, position: Vector3<i32>) -> Option<&mut Chunk<T, M, X, Y, Z>> { self.chunks.get_mut(&position) }
+    pub fn is_loaded(&self, position: Vector3<i32>) -> bool { self.chunks.contains_key(&position) }
+    pub fn chunks(&self) -> impl Iterator<Item = (&Vector3<i32>, &Chunk<T, M, X, Y, Z>)> { self.chunks.iter() }
+    pub fn chunks_mut(&mut self) -> impl Iterator<Item = (&Vector3<i32>, &mut Chunk<T, M, X, Y, Z>)> { self.chunks.iter_mut() }
+    pub fn len(&self) -> usize { self.chunks.len() }
+    pub fn is_empty(&self) -> bool { self.chunks.is_empty() }
+
+    /// The grid position of the chunk the viewer was in at the last update.
+    pub fn center(&self) -> Vector3<i32> { self.center }
+
+    /// Gets the grid position of the chunk containing the given world position.
+    pub fn grid_position(position: Vector3<f32>) -> Vector3<i32> {
+        Vector3 { x: (position.x / X as f32).floor() as i32, y: (position.y / Y as f32).floor() as i32, z: (position.z / Z as f32).floor() as i32 }
+    }
+
+    /// Returns true if the given grid position is within the radii around the given center.
+    pub fn in_range(&self, center: Vector3<i32>, position: Vector3<i32>) -> bool {
+        let offset = position - center;
+        offset.x * offset.x + offset.z * offset.z <= self.horizontal_radius * self.horizontal_radius && offset.y.abs() <= self.vertical_radius
+    }
+
+    /// Unloads chunks out of range of the viewer, then loads up to `load_budget` missing chunks in range, closest first.
+    ///
+    /// Arguments:
+    /// * viewer: Vector3<f32> - The world position of the viewer, like the cameras position.
+    ///
+    /// Returns the grid positions of the chunks loaded.
+    pub fn update(&mut self, viewer: Vector3<f32>) -> Vec<Vector3<i32>> {
+        let center = Self::grid_position(viewer);
+        self.center = center;
+
+        // unload chunks that are out of range
+        let unload: Vec<Vector3<i32>> = self.chunks.keys().filter(|position| !self.in_range(center, **position)).copied().collect();
+        unload.into_iter().for_each(|position| self.unload(position));
+
+        // find missing chunks in range, closest first
+        let mut missing = Vec::new();
+        for x in -self.horizontal_radius ..= self.horizontal_radius {
+            for y in -self.vertical_radius ..= self.vertical_radius {
+                for z in -self.horizontal_radius ..= self.horizontal_radius {
+                    let position = center + Vector3 { x, y, z };
+                    if self.in_range(center, position) && !self.chunks.contains_key(&position) { missing.push(position); }
+                }
+            }
+        }
+        missing.sort_by_key(|position| (position - center).magnitude2());
+        missing.truncate(self.load_budget);
+
+        // load the closest missing chunks
+        for position in missing.iter() {
+            let mut chunk = self.provider.load(*position);
+            chunk.id = self.next_id;
+            chunk.transform.position = Vector3 { x: position.x * X as i32, y: position.y * Y as i32, z: position.z * Z as i32 }.cast::<f32>().unwrap();
+            self.next_id = self.next_id.wrapping_add(1);
+            self.chunks.insert(*position, chunk);
+        }
+
+        // join the surfaces of smooth chunks to the chunks just loaded next to them
+        self.pending_borders.extend(missing.iter().copied());
+        self.refresh_pending_borders();
+        missing
+    }
+
+    /// Updates the borders of the smooth chunk at the given grid position and the smooth chunks around it from the blocks of their loaded neighbours, see `Chunk::set_border`.
+    /// This is done automatically when chunks are loaded and when blocks are set with `BlockWorld::set_block_at`, so only call it after changing blocks on the edges of smooth chunks through `get_mut`.
+    pub fn refresh_borders(&mut self, position: Vector3<i32>) {
+        self.pending_borders.insert(position);
+        self.refresh_pending_borders();
+    }
+
+    // Marks the borders around the chunk at the given grid position to be updated by the next `refresh_pending_borders`.
+    pub(crate) fn defer_borders(&mut self, position: Vector3<i32>) { self.pending_borders.insert(position); }
+
+    // Updates the borders around every chunk marked by `defer_borders`, updating each smooth chunk once no matter how many of its neighbours changed.
+    pub(crate) fn refresh_pending_borders(&mut self) {
+        let mut around = HashSet::new();
+        for position in self.pending_borders.drain() {
+            for x in -1 ..= 1 {
+                for y in -1 ..= 1 {
+                    for z in -1 ..= 1 {
+                        around.insert(position + Vector3 { x, y, z });
+                    }
+                }
+            }
+        }
+
+        for position in around {
+            if self.chunks.get(&position).is_none_or(|chunk| chunk.mesher() != ChunkMesher::Smooth) { continue }
+
+            // take the chunk out while its neighbours are read
+            let mut chunk = self.chunks.remove(&position).unwrap();
+            let origin = Vector3 { x: position.x * X as i32, y: position.y * Y as i32, z: position.z * Z as i32 };
+            chunk.set_border(|location| self.block_at(origin + location).map(|(material, data)| (material.into(), data)));
+            self.chunks.insert(position, chunk);
+        }
+    }
+
+    /// Meshes up to `mesh_budget` chunks that changed since they were last meshed, closest to the viewer first.
+    ///
+    /// Arguments:
+    /// * engine: &RenderEngine - The engine used to create the mesh buffers.
+    /// * resources: RenderResources - The resources used to render blocks.
+    ///
+    /// Returns the number of chunks meshed.
+    pub fn mesh(&mut self, engine: &RenderEngine, resources: RenderResources) -> usize {
+        let center = self.center;
+        let mut dirty: Vec<Vector3<i32>> = self.chunks.iter().filter(|(_, chunk)| chunk.is_dirty()).map(|(position, _)| *position).collect();
+        dirty.sort_by_key(|position| (position - center).magnitude2());
+        dirty.truncate(self.mesh_budget);
+
+        dirty.iter().for_each(|position| self.chunks.get_mut(position).unwrap().gen_mesh(engine, resources));
+        dirty.len()
+    }
+
+    /// Unloads the chunk at the given grid position, saving it if `save_on_unload` is true.  Does nothing if the chunk is not loaded.
+    pub fn unload(&mut self, position: Vector3<i32>) {
+        if let Some(chunk) = self.chunks.remove(&position) {
+            if self.save_on_unload { self.provider.save(position, &chunk); }
+        }
+    }
+
+    /// Unloads every chunk, saving them if `save_on_unload` is true.  Call this before exiting so no changes are lost.
+    pub fn unload_all(&mut self) {
+        let positions: Vec<Vector3<i32>> = self.chunks.keys().copied().collect();
+        positions.into_iter().for_each(|position| self.unload(position));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{define_blocks_materials, terrain::atlas::Tile};
+
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        AIR => { transparent: true, renderer: BlockRenderer::None },
+        STONE => { transparent: false, renderer: BlockRenderer::Standard(Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0)) }
+    ]);
+
+    type TestChunk = Chunk<Blocks, Material, 4, 4, 4>;
+
+    // records the chunks loaded and saved
+    #[derive(Default)]
+    struct Recorder {
+        loaded: Vec<Vector3<i32>>,
+        saved: Vec<Vector3<i32>>
+    }
+
+    impl ChunkProvider<Blocks, Material, 4, 4, 4> for Recorder {
+        fn load(&mut self, position: Vector3<i32>) -> TestChunk {
+            self.loaded.push(position);
+            Chunk::empty(0)
+        }
+
+        fn save(&mut self, position: Vector3<i32>, _chunk: &TestChunk) { self.saved.push(position); }
+    }
+
+    #[test]
+    fn chunks_load_closest_first_within_the_budget() {
+        let mut streamer = ChunkStreamer::new(Recorder::default(), 2, 0).with_budget(5, 1);
+
+        // the 13 chunks in range take three updates
+        let first = streamer.update(Vector3::new(2.0, 2.0, 2.0));
+        assert_eq!(first.len(), 5);
+        assert_eq!(first[0], Vector3::zero());
+        assert!(first[1 ..].iter().all(|position| position.magnitude2() == 1));
+        assert_eq!(streamer.provider().loaded, first);
+        assert_eq!(streamer.update(Vector3::new(2.0, 2.0, 2.0)).len(), 5);
+        assert_eq!(streamer.update(Vector3::new(2.0, 2.0, 2.0)).len(), 3);
+        assert!(streamer.update(Vector3::new(2.0, 2.0, 2.0)).is_empty());
+        assert_eq!(streamer.len(), 13);
+
+        // loaded chunks are placed at their grid position with unique ids
+        let chunk = streamer.get(Vector3::new(-1, 0, 0)).unwrap();
+        assert_eq!(chunk.transform.position, Vector3::new(-4.0, 0.0, 0.0));
+        let mut ids: Vec<u32> = streamer.chunks().map(|(_, chunk)| chunk.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 13);
+    }
+
+    #[test]
+    fn chunks_out_of_range_are_unloaded_and_saved() {
+        let mut streamer = ChunkStreamer::new(Recorder::default(), 1, 0).with_budget(16, 1).with_saving(true);
+        streamer.update(Vector3::new(2.0, 2.0, 2.0));
+        assert_eq!(streamer.len(), 5);
+
+        // moving two chunks east leaves only the chunk east of the start in range
+        streamer.update(Vector3::new(10.0, 2.0, 2.0));
+        let mut saved = streamer.provider().saved.clone();
+        saved.sort_by_key(|position| (position.x, position.z));
+        assert_eq!(saved, vec![Vector3::new(-1, 0, 0), Vector3::new(0, 0, -1), Vector3::new(0, 0, 0), Vector3::new(0, 0, 1)]);
+        assert!(streamer.is_loaded(Vector3::new(1, 0, 0)));
+        assert_eq!(streamer.center(), Vector3::new(2, 0, 0));
+        assert_eq!(streamer.len(), 5);
+
+        // without saving, unloading does not reach the provider
+        streamer.save_on_unload = false;
+        streamer.unload_all();
+        assert!(streamer.is_empty());
+        assert_eq!(streamer.provider().saved.len(), 4);
+    }
+}
@@ -0,0 +1,222 @@
+use std::{cmp::{Ordering, Reverse}, collections::BinaryHeap};
+
+use cgmath::*;
+
+use crate::terrain::{blocks::*, chunk::{BlockStorage, Chunk}};
+
+/// The height of a chunk section, in blocks.  Each section of a chunk receives its own random ticks.
+pub const SECTION_SIZE: usize = 16;
+
+/// The callback type a `BlockDef` can provide to be updated by a `TickScheduler`.
+pub type TickCallback<M> = fn(&mut TickContext<M>);
+
+/// The reason a block was ticked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickKind {
+    /// The tick was scheduled by `TickScheduler::schedule`.
+    Scheduled,
+    /// The tick was chosen randomly by the `TickScheduler`.
+    Random
+}
+
+/// A tick that is scheduled to happen on a block in the future.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduledTick {
+    /// The tick on which this should be run.
+    pub time: u64,
+    /// The priority of this tick.  Lower priorities are run first when multiple ticks are run on the same tick.
+    pub priority: i32,
+    /// The id of the chunk containing the block.
+    pub chunk: u32,
+    /// The position of the block in its chunk.
+    pub position: Vector3<usize>,
+    order: u64
+}
+
+impl Ord for ScheduledTick {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.time.cmp(&other.time)
+            .then(self.priority.cmp(&other.priority))
+            .then(self.order.cmp(&other.order))
+    }
+}
+
+impl PartialOrd for ScheduledTick {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+/// The information given to a `TickCallback` when a block is ticked.
+pub struct TickContext<'a, M: MaterialDef + 'static> {
+    /// Why this block was ticked.
+    pub kind: TickKind,
+    /// The id of the chunk containing the ticked block.
+    pub chunk_id: u32,
+    /// The position of the ticked block in its chunk.
+    pub position: Vector3<usize>,
+    /// The chunk containing the ticked block.
+    pub chunk: &'a mut dyn BlockStorage<M>,
+    /// The scheduler running this tick.
+    pub scheduler: &'a mut TickScheduler
+}
+
+impl <'a, M: MaterialDef + 'static> TickContext<'a, M> {
+    /// Schedules a tick for a block in the same chunk as the ticked block.
+    ///
+    /// Arguments:
+    /// * position: Vector3<usize> - The position of the block to tick.
+    /// * delay: u64 - The number of ticks to wait.  This will always be at least 1.
+    /// * priority: i32 - The priority of the tick.
+    pub fn schedule(&mut self, position: Vector3<usize>, delay: u64, priority: i32) {
+        self.scheduler.schedule(self.chunk_id, position, delay, priority);
+    }
+}
+
+/// Runs scheduled and random block ticks over a set of chunks.
+///
+/// All randomness comes from the seed given on creation so that ticking is deterministic.
+#[derive(Debug, Clone)]
+pub struct TickScheduler {
+    /// The number of random blocks ticked in each chunk section per step.
+    pub random_ticks_per_section: usize,
+    time: u64,
+    counter: u64,
+    rng: u64,
+    queue: BinaryHeap<Reverse<ScheduledTick>>
+}
+
+impl TickScheduler {
+    /// Creates a new tick scheduler.
+    ///
+    /// Arguments:
+    /// * seed: u64 - The seed for choosing random ticks.
+    /// * random_ticks_per_section: usize - The number of random blocks ticked in each chunk section per step.
+    pub fn new(seed: u64, random_ticks_per_section: usize) -> Self {
+        Self { random_ticks_per_section, time: 0, counter: 0, rng: seed, queue: BinaryHeap::new() }
+    }
+
+    /// The current tick.
+    pub fn time(&self) -> u64 { self.time }
+
+    /// The number of scheduled ticks that have not run yet.
+    pub fn pending(&self) -> usize { self.queue.len() }
+
+    /// Schedules a block to be ticked in the future.
+    ///
+    /// Arguments:
+    /// * chunk: u32 - The id of the chunk containing the block.
+    /// * position: Vector3<usize> - The position of the block in the chunk.
+    /// * delay: u64 - The number of ticks to wait.  This will always be at least 1.
+    /// * priority: i32 - The priority of the tick.  Lower priorities are run first.
+    pub fn schedule(&mut self, chunk: u32, position: Vector3<usize>, delay: u64, priority: i32) {
+        let order = self.counter;
+        self.counter += 1;
+        self.queue.push(Reverse(ScheduledTick { time: self.time + delay.max(1), priority, chunk, position, order }));
+    }
+
+    /// Returns the next random number from this schedulers seeded generator (splitmix64).
+    pub fn random(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Advances this scheduler by one tick, running all scheduled ticks that are due and then the random ticks for every chunk section.
+    ///
+    /// Arguments:
+    /// * chunks: &mut [Chunk<T, M, X, Y, Z>] - The chunks to tick.  Scheduled ticks for chunks not in this list are dropped.
+    pub fn step<T: BlockDefinitions<M>, M: MaterialDef + 'static, const X: usize, const Y: usize, const Z: usize>(&mut self, chunks: &mut [Chunk<T, M, X, Y, Z>]) {
+        self.time += 1;
+
+        // collect due ticks first so ticks scheduled while running do not run this step
+        let mut due = Vec::new();
+        while self.queue.peek().is_some_and(|tick| tick.0.time <= self.time) {
+            due.push(self.queue.pop().unwrap().0);
+        }
+
+        // run scheduled ticks
+        for tick in due {
+            let chunk = chunks.iter_mut().find(|chunk| chunk.id == tick.chunk);
+            if let Some(chunk) = chunk { self.tick_block(chunk, tick.position, TickKind::Scheduled); }
+        }
+
+        // run random ticks
+        if self.random_ticks_per_section == 0 { return }
+        for chunk in chunks.iter_mut() {
+            let size = chunk.size();
+            let sections = size.y.div_ceil(SECTION_SIZE);
+            for section in 0 .. sections {
+                // the last section is shorter if the chunk height is not a multiple of the section size
+                let height = SECTION_SIZE.min(size.y - section * SECTION_SIZE);
+                for _ in 0 .. self.random_ticks_per_section {
+                    let position = Vector3 {
+                        x: (self.random() % size.x as u64) as usize,
+                        y: section * SECTION_SIZE + (self.random() % height as u64) as usize,
+                        z: (self.random() % size.z as u64) as usize
+                    };
+                    self.tick_block(chunk, position, TickKind::Random);
+                }
+            }
+        }
+    }
+
+    fn tick_block<T: BlockDefinitions<M>, M: MaterialDef + 'static, const X: usize, const Y: usize, const Z: usize>(&mut self, chunk: &mut Chunk<T, M, X, Y, Z>, position: Vector3<usize>, kind: TickKind) {
+        // get the tick callback of the block, if any
+        let material: u16 = chunk.get(position).into();
+        let callback = match T::DEFINITIONS[material as usize].tick {
+            Some(callback) => callback,
+            None => return
+        };
+
+        // run the callback
+        let chunk_id = chunk.id;
+        callback(&mut TickContext { kind, chunk_id, position, chunk, scheduler: self });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{define_blocks_materials, terrain::atlas::Tile};
+
+    thread_local! {
+        static TICKED: RefCell<Vec<(TickKind, Vector3<usize>)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(context: &mut TickContext<Material>) {
+        TICKED.with(|ticked| ticked.borrow_mut().push((context.kind, context.position)));
+    }
+
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        AIR => { transparent: true, renderer: BlockRenderer::None, tick: record },
+        STONE => { transparent: false, renderer: BlockRenderer::Standard(Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0), Tile::Index(0)) }
+    ]);
+
+    // runs a scheduler with the given seed for a few steps and returns every block it ticked in order
+    fn run(seed: u64) -> Vec<(TickKind, Vector3<usize>)> {
+        TICKED.with(|ticked| ticked.borrow_mut().clear());
+        let mut chunks = vec![Chunk::<Blocks, Material, 8, 24, 8>::empty(0)];
+        let mut scheduler = TickScheduler::new(seed, 3);
+        scheduler.schedule(0, Vector3 { x: 1, y: 2, z: 3 }, 2, 0);
+        for _ in 0 .. 50 { scheduler.step(&mut chunks); }
+        TICKED.with(|ticked| ticked.take())
+    }
+
+    #[test]
+    fn same_seed_ticks_same_blocks() {
+        let first = run(42);
+        assert_eq!(first, run(42));
+        assert_ne!(first, run(7));
+        assert!(first.contains(&(TickKind::Scheduled, Vector3 { x: 1, y: 2, z: 3 })));
+    }
+
+    #[test]
+    fn random_ticks_reach_partial_sections() {
+        let ticked = run(42);
+        assert!(ticked.iter().all(|(_, position)| position.y < 24));
+        assert!(ticked.iter().any(|(kind, position)| *kind == TickKind::Random && position.y >= SECTION_SIZE));
+    }
+}
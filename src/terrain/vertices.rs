@@ -0,0 +1,70 @@
+use forte_engine::primitives::vertices::Vertex;
+
+/// A vertex of a chunk mesh.  This is the same as the engines `Vertex`, but with the layer of the `BlockAtlas` that it samples, its tile animation and a colour its texture is multiplied by.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChunkVertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    pub layer: u32,
+    /// The index of the `TileAnimation` of this vertex in its atlas plus 1, or 0 if it is not animated.
+    pub animation: u32,
+    /// The RGB colour the texture is multiplied by.  This is white unless the face is tinted.
+    pub color: [f32; 3],
+    /// 1 if the texture is projected onto the mesh from the world position along all three axes and blended by the normal, ignoring `tex_coords`, or 0 otherwise.  This is used by smooth terrain, see `ChunkMesher::Smooth`.
+    pub triplanar: u32
+}
+
+impl ChunkVertex {
+    /// Creates a chunk vertex that is not animated from an engine `Vertex` and the atlas layer it samples.
+    pub fn from_vertex(vertex: &Vertex, layer: u32) -> Self {
+        Self { position: vertex.position, tex_coords: vertex.tex_coords, normal: vertex.normal, layer, animation: 0, color: [1.0, 1.0, 1.0], triplanar: 0 }
+    }
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+                // locations 5 to 11 are used by the instance transform, so continue at 12
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 10]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 13]>() as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+            ],
+        }
+    }
+}
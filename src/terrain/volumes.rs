@@ -0,0 +1,249 @@
+use cgmath::*;
+
+use crate::terrain::{blocks::*, edits::{BlockWorld, EditTransaction}, states::{Axis, BlockState}};
+
+/// A box of world positions between two corners, including both corners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Region {
+    pub min: Vector3<i32>,
+    pub max: Vector3<i32>
+}
+
+impl Region {
+    /// Creates the region between the two given corners, in any order.
+    pub fn new(a: Vector3<i32>, b: Vector3<i32>) -> Self {
+        Self {
+            min: Vector3 { x: a.x.min(b.x), y: a.y.min(b.y), z: a.z.min(b.z) },
+            max: Vector3 { x: a.x.max(b.x), y: a.y.max(b.y), z: a.z.max(b.z) }
+        }
+    }
+
+    /// The size of this region in blocks.
+    pub fn size(&self) -> Vector3<usize> { (self.max - self.min + Vector3::from_value(1)).cast::<usize>().unwrap() }
+
+    /// Returns true if the given position is inside this region.
+    pub fn contains(&self, position: Vector3<i32>) -> bool {
+        position.x >= self.min.x && position.y >= self.min.y && position.z >= self.min.z
+            && position.x <= self.max.x && position.y <= self.max.y && position.z <= self.max.z
+    }
+
+    /// Returns true if the given position is on the outer shell of this region.
+    pub fn on_shell(&self, position: Vector3<i32>) -> bool {
+        self.contains(position) && (
+            position.x == self.min.x || position.y == self.min.y || position.z == self.min.z
+            || position.x == self.max.x || position.y == self.max.y || position.z == self.max.z
+        )
+    }
+
+    /// Iterates over every position in this region, in x, then y, then z order.
+    pub fn iter(&self) -> impl Iterator<Item = Vector3<i32>> {
+        let (min, max) = (self.min, self.max);
+        (min.x ..= max.x).flat_map(move |x| (min.y ..= max.y).flat_map(move |y| (min.z ..= max.z).map(move |z| Vector3 { x, y, z })))
+    }
+}
+
+/// Bulk edits over regions that may span many chunks.  Every block changed is recorded in the transaction so the whole operation can be undone at once.
+/// Each operation returns the number of blocks that were loaded and set.  Follow up work like updating the borders of smooth chunks is done once at the end of each operation, see `BlockWorld::set_block_at_deferred`.
+impl <M: MaterialDef + 'static> EditTransaction<M> {
+    /// Sets every block in the given region.
+    pub fn fill(&mut self, world: &mut impl BlockWorld<M>, region: Region, material: M, data: u16) -> usize {
+        let count = region.iter().filter(|position| self.set_deferred(world, *position, material, data)).count();
+        world.finish_edits();
+        count
+    }
+
+    /// Sets every block on the outer shell of the given region, leaving the blocks inside alone.
+    pub fn hollow(&mut self, world: &mut impl BlockWorld<M>, region: Region, material: M, data: u16) -> usize {
+        let count = region.iter().filter(|position| region.on_shell(*position)).filter(|position| self.set_deferred(world, *position, material, data)).count();
+        world.finish_edits();
+        count
+    }
+
+    /// Replaces every block of the material `from` in the given region with the material `to` and the given data.
+    pub fn replace(&mut self, world: &mut impl BlockWorld<M>, region: Region, from: M, to: M, data: u16) -> usize {
+        let from: u16 = from.into();
+        let count = region.iter()
+            .filter(|position| {
+                let matches = world.block_at(*position).is_some_and(|(material, _)| material.into() == from);
+                matches && self.set_deferred(world, *position, to, data)
+            })
+            .count();
+        world.finish_edits();
+        count
+    }
+
+    /// Sets every block whose center is within the given radius of the center of the given block.
+    pub fn sphere(&mut self, world: &mut impl BlockWorld<M>, center: Vector3<i32>, radius: f32, material: M, data: u16) -> usize {
+        let reach = radius.ceil() as i32;
+        let region = Region::new(center - Vector3::from_value(reach), center + Vector3::from_value(reach));
+        let count = region.iter()
+            .filter(|position| (position - center).cast::<f32>().unwrap().magnitude2() <= radius * radius)
+            .filter(|position| self.set_deferred(world, *position, material, data))
+            .count();
+        world.finish_edits();
+        count
+    }
+
+    /// Sets every block in an upright cylinder standing on the given block, whose center is within the given radius of the cylinders axis.
+    pub fn cylinder(&mut self, world: &mut impl BlockWorld<M>, base: Vector3<i32>, radius: f32, height: u32, material: M, data: u16) -> usize {
+        if height == 0 { return 0 }
+        let reach = radius.ceil() as i32;
+        let region = Region::new(base - Vector3 { x: reach, y: 0, z: reach }, base + Vector3 { x: reach, y: height as i32 - 1, z: reach });
+        let count = region.iter()
+            .filter(|position| Vector2 { x: (position.x - base.x) as f32, y: (position.z - base.z) as f32 }.magnitude2() <= radius * radius)
+            .filter(|position| self.set_deferred(world, *position, material, data))
+            .count();
+        world.finish_edits();
+        count
+    }
+
+    /// Pastes the given clipboard with its minimum corner at the given position.  Blocks that were not loaded when copied are skipped, as is air (material 0) if `skip_air` is true.
+    pub fn paste(&mut self, world: &mut impl BlockWorld<M>, clipboard: &Clipboard<M>, origin: Vector3<i32>, skip_air: bool) -> usize {
+        let mut count = 0;
+        for (location, (material, data)) in clipboard.iter() {
+            if skip_air && material.into() == 0 { continue }
+            if self.set_deferred(world, origin + location.cast::<i32>().unwrap(), material, data) { count += 1; }
+        }
+        world.finish_edits();
+        count
+    }
+}
+
+/// An in-memory copy of the blocks in a region, that can be turned and mirrored before being pasted with `EditTransaction::paste`.
+#[derive(Debug, Clone)]
+pub struct Clipboard<M: MaterialDef + 'static> {
+    size: Vector3<usize>,
+    blocks: Vec<Option<(M, u16)>>
+}
+
+impl <M: MaterialDef + 'static> Clipboard<M> {
+    /// Creates a clipboard of the given size with no blocks.
+    pub fn empty(size: Vector3<usize>) -> Self { Self { size, blocks: vec![None; size.x * size.y * size.z] } }
+
+    /// Copies the blocks in the given region.  Blocks that are not loaded are left empty.
+    pub fn copy(world: &impl BlockWorld<M>, region: Region) -> Self {
+        let mut clipboard = Self::empty(region.size());
+        for position in region.iter() {
+            clipboard.set((position - region.min).cast::<usize>().unwrap(), world.block_at(position));
+        }
+        clipboard
+    }
+
+    /// The size of this clipboard in blocks.
+    pub fn size(&self) -> Vector3<usize> { self.size }
+
+    /// Gets the material and data of the block at the given location, or `None` if it is empty.
+    pub fn get(&self, location: Vector3<usize>) -> Option<(M, u16)> { self.blocks[self.index(location)] }
+
+    /// Sets the material and data of the block at the given location, or empties it with `None`.
+    pub fn set(&mut self, location: Vector3<usize>, block: Option<(M, u16)>) { let index = self.index(location); self.blocks[index] = block; }
+
+    /// Iterates over every block that is not empty and its location.
+    pub fn iter(&self) -> impl Iterator<Item = (Vector3<usize>, (M, u16))> + '_ {
+        let size = self.size;
+        self.blocks.iter().enumerate().filter_map(move |(index, block)| {
+            let location = Vector3 { x: index / (size.y * size.z), y: (index / size.z) % size.y, z: index % size.z };
+            block.map(|block| (location, block))
+        })
+    }
+
+    /// Returns a copy of this clipboard turned the given number of quarter turns around the Y axis, where one turn takes north to east.  The states of the blocks are turned with it.
+    pub fn rotate_y<T: BlockDefinitions<M>>(&self, turns: u32) -> Self {
+        let turns = turns % 4;
+        let size = if turns % 2 == 1 { Vector3 { x: self.size.z, y: self.size.y, z: self.size.x } } else { self.size };
+        let mut result = Self::empty(size);
+        for (location, (material, data)) in self.iter() {
+            let turned = rotate_location(location, self.size, turns);
+            let state = BlockState::new(T::DEFINITIONS[material.into() as usize].properties, data).rotate_y(turns);
+            result.set(turned, Some((material, state.data)));
+        }
+        result
+    }
+
+    /// Returns a copy of this clipboard mirrored across the plane perpendicular to the given axis.  The states of the blocks are mirrored with it.
+    pub fn mirror<T: BlockDefinitions<M>>(&self, axis: Axis) -> Self {
+        let mut result = Self::empty(self.size);
+        for (location, (material, data)) in self.iter() {
+            let mut mirrored = location;
+            match axis {
+                Axis::X => mirrored.x = self.size.x - 1 - location.x,
+                Axis::Y => mirrored.y = self.size.y - 1 - location.y,
+                Axis::Z => mirrored.z = self.size.z - 1 - location.z
+            }
+            let state = BlockState::new(T::DEFINITIONS[material.into() as usize].properties, data).mirror(axis);
+            result.set(mirrored, Some((material, state.data)));
+        }
+        result
+    }
+
+    fn index(&self, location: Vector3<usize>) -> usize { (location.x * self.size.y + location.y) * self.size.z + location.z }
+}
+
+// Turns a location in a box of the given size by the given number of quarter turns around the Y axis, keeping it inside the turned box.
+pub(crate) fn rotate_location(location: Vector3<usize>, size: Vector3<usize>, turns: u32) -> Vector3<usize> {
+    // turn one quarter at a time, where (x, z) becomes (z, -x)
+    let mut turned = location;
+    let mut current = size;
+    for _ in 0 .. turns % 4 {
+        turned = Vector3 { x: turned.z, y: turned.y, z: current.x - 1 - turned.x };
+        current = Vector3 { x: current.z, y: current.y, z: current.x };
+    }
+    turned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{define_blocks_materials, terrain::states::{BlockProperty, Direction, PropertyValue}};
+
+    define_blocks_materials!(Blocks, Material, "test.png", [
+        AIR => { transparent: true, renderer: BlockRenderer::None },
+        STONE => { transparent: false, renderer: BlockRenderer::None },
+        FURNACE => { transparent: false, renderer: BlockRenderer::None, properties: [BlockProperty::HorizontalFacing] }
+    ]);
+
+    // a 2x1x3 clipboard with stone in one corner and a furnace facing north in the opposite corner
+    fn clipboard() -> Clipboard<Material> {
+        let furnace = BlockState::default(Blocks::DEFINITIONS[Material::FURNACE as usize].properties).with("facing", PropertyValue::Direction(Direction::North));
+        let mut clipboard = Clipboard::empty(Vector3::new(2, 1, 3));
+        clipboard.set(Vector3::new(0, 0, 0), Some((Material::STONE, 0)));
+        clipboard.set(Vector3::new(1, 0, 2), Some((Material::FURNACE, furnace.data)));
+        clipboard
+    }
+
+    fn facing(clipboard: &Clipboard<Material>, location: Vector3<usize>) -> Option<Direction> {
+        let (material, data) = clipboard.get(location)?;
+        BlockState::new(Blocks::DEFINITIONS[material as usize].properties, data).facing()
+    }
+
+    fn blocks(clipboard: &Clipboard<Material>) -> Vec<(Vector3<usize>, u16, u16)> {
+        clipboard.iter().map(|(location, (material, data))| (location, material.into(), data)).collect()
+    }
+
+    #[test]
+    fn rotating_turns_locations_and_states() {
+        let turned = clipboard().rotate_y::<Blocks>(1);
+        assert_eq!(turned.size(), Vector3::new(3, 1, 2));
+        assert!(matches!(turned.get(Vector3::new(0, 0, 1)), Some((Material::STONE, 0))));
+        assert_eq!(facing(&turned, Vector3::new(2, 0, 0)), Some(Direction::East));
+        assert_eq!(turned.iter().count(), 2);
+
+        // four turns come back to the start
+        let clipboard = clipboard();
+        assert_eq!(blocks(&clipboard.rotate_y::<Blocks>(4)), blocks(&clipboard));
+        assert_eq!(blocks(&turned.rotate_y::<Blocks>(3)), blocks(&clipboard));
+    }
+
+    #[test]
+    fn mirroring_flips_locations_and_states() {
+        let turned = clipboard().rotate_y::<Blocks>(1);
+        let mirrored = turned.mirror::<Blocks>(Axis::X);
+        assert_eq!(mirrored.size(), turned.size());
+        assert!(matches!(mirrored.get(Vector3::new(2, 0, 1)), Some((Material::STONE, 0))));
+        assert_eq!(facing(&mirrored, Vector3::new(0, 0, 0)), Some(Direction::West));
+
+        // mirroring along z leaves east and west facing alone
+        assert_eq!(facing(&turned.mirror::<Blocks>(Axis::Z), Vector3::new(2, 0, 1)), Some(Direction::East));
+        assert_eq!(blocks(&mirrored.mirror::<Blocks>(Axis::X)), blocks(&turned));
+    }
+}
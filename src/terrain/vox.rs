@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use cgmath::*;
+
+use crate::{terrain::{blocks::*, chunk::Chunk, edits::BlockWorld}, vox::VoxModel};
+
+impl VoxModel {
+    /// Converts this model into chunks, splitting it across chunk boundaries.  Only chunks that contain at least one voxel are created.
+    ///
+    /// Arguments:
+    /// * offset: Vector3<i32> - The world position of the minimum corner of the model.
+    /// * material: impl Fn(u8) -> Option<M> - Maps a colour index to the material of its blocks, or `None` to leave voxels of that colour out.
+    ///
+    /// Returns the created chunks keyed by their grid position, with their transforms set to their world positions and ids given in order of creation.
+    pub fn to_chunks<T: BlockDefinitions<M>, M: MaterialDef + 'static, const X: usize, const Y: usize, const Z: usize>(&self, offset: Vector3<i32>, material: impl Fn(u8) -> Option<M>) -> HashMap<Vector3<i32>, Chunk<T, M, X, Y, Z>> {
+        let size = Vector3 { x: X as i32, y: Y as i32, z: Z as i32 };
+        let mut chunks: HashMap<Vector3<i32>, Chunk<T, M, X, Y, Z>> = HashMap::new();
+
+        for (location, colour) in self.voxels.iter() {
+            let material = match material(*colour) { Some(material) => material, None => continue };
+            let position = offset + location.cast::<i32>().unwrap();
+            let grid = Vector3 { x: position.x.div_euclid(size.x), y: position.y.div_euclid(size.y), z: position.z.div_euclid(size.z) };
+            let local = Vector3 { x: position.x.rem_euclid(size.x), y: position.y.rem_euclid(size.y), z: position.z.rem_euclid(size.z) };
+
+            let id = chunks.len() as u32;
+            let chunk = chunks.entry(grid).or_insert_with(|| {
+                let mut chunk = Chunk::empty(id);
+                chunk.transform.position = Vector3 { x: grid.x * size.x, y: grid.y * size.y, z: grid.z * size.z }.cast::<f32>().unwrap();
+                chunk
+            });
+            chunk.set(local.cast::<usize>().unwrap(), material, 0);
+        }
+        chunks
+    }
+
+    /// Writes the voxels of this model into the given world, like a `ChunkStreamer`.  Voxels in chunks that are not loaded are skipped.
+    ///
+    /// Arguments:
+    /// * world: &mut impl BlockWorld<M> - The world to write to.
+    /// * offset: Vector3<i32> - The world position of the minimum corner of the model.
+    /// * material: impl Fn(u8) -> Option<M> - Maps a colour index to the material of its blocks, or `None` to leave voxels of that colour out.
+    ///
+    /// Returns the number of blocks set.
+    pub fn write_to<M: MaterialDef + 'static>(&self, world: &mut impl BlockWorld<M>, offset: Vector3<i32>, material: impl Fn(u8) -> Option<M>) -> usize {
+        let count = self.voxels.iter()
+            .filter_map(|(location, colour)| Some((offset + location.cast::<i32>().unwrap(), material(*colour)?)))
+            .filter(|(position, material)| world.set_block_at_deferred(*position, *material, 0))
+            .count();
+        world.finish_edits();
+        count
+    }
+}
@@ -0,0 +1,228 @@
+use std::{fmt::Display, fs};
+
+use cgmath::*;
+
+/// The errors that can occur while loading a MagicaVoxel `.vox` file or converting its models.
+#[derive(Debug)]
+pub enum VoxError {
+    /// A file could not be read.
+    Io(String, std::io::Error),
+    /// The data does not start with the "VOX " magic or has no "MAIN" chunk.
+    NotVox,
+    /// The data ended in the middle of a chunk.
+    Truncated,
+    /// A "XYZI" chunk was found without a "SIZE" chunk before it.
+    MissingSize,
+    /// A voxel is outside of the size of its model.
+    OutOfBounds { model: usize, position: [u8; 3] },
+    /// A model index is not in the file.
+    MissingModel(usize),
+    /// The palette texture of a model could not be encoded.
+    Image(image::ImageError)
+}
+
+impl Display for VoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "Could not read vox file {}: {}", path, err),
+            Self::NotVox => write!(f, "Data is not a MagicaVoxel vox file"),
+            Self::Truncated => write!(f, "Vox data ended in the middle of a chunk"),
+            Self::MissingSize => write!(f, "Vox model voxels were given before the size of the model"),
+            Self::OutOfBounds { model, position } => write!(f, "Vox model {} has a voxel at {:?} outside of its size", model, position),
+            Self::MissingModel(model) => write!(f, "Vox file has no model {}", model),
+            Self::Image(err) => write!(f, "Could not encode vox palette texture: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for VoxError {}
+
+/// A single model of a `VoxFile`.
+///
+/// MagicaVoxel uses Z as up, so models are converted to Y up when loaded, where the MagicaVoxel Z axis becomes Y and the MagicaVoxel Y axis becomes Z flipped, so models are not mirrored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoxModel {
+    /// The size of the model in voxels.
+    pub size: Vector3<usize>,
+    /// Every voxel in the model as its location and colour index.  Colour indices start at 1, with 0 meaning empty.
+    pub voxels: Vec<(Vector3<usize>, u8)>
+}
+
+/// A MagicaVoxel `.vox` file, with its models and colour palette.
+///
+/// Only the "SIZE", "XYZI" and "RGBA" chunks are read.  Scene chunks, like the transforms of models, materials and layers are skipped.
+///
+/// Example
+/// ```rust,ignore
+/// let file = VoxFile::load("assets/castle.vox")?;
+/// for model in file.models.iter() { println!("model of size {:?} with {} voxels", model.size, model.voxels.len()); }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoxFile {
+    /// The models in the order they appear in the file.
+    pub models: Vec<VoxModel>,
+    /// The RGBA colour of every colour index.  Index 0 is unused.  Files without a "RGBA" chunk use the MagicaVoxel default palette.
+    pub palette: [[u8; 4]; 256]
+}
+
+impl VoxFile {
+    /// Loads a vox file from the given path.
+    pub fn load(path: &str) -> Result<Self, VoxError> {
+        let bytes = fs::read(path).map_err(|err| VoxError::Io(path.to_string(), err))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Parses a vox file from its bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VoxError> {
+        if bytes.len() < 8 || &bytes[0 .. 4] != b"VOX " { return Err(VoxError::NotVox) }
+        let mut reader = VoxReader { bytes, cursor: 8 };
+
+        // the main chunk has no content, with every other chunk as its children
+        let (id, content, children) = reader.chunk_header()?;
+        if id != *b"MAIN" { return Err(VoxError::NotVox) }
+        reader.skip(content)?;
+        let end = reader.cursor.checked_add(children).filter(|end| *end <= bytes.len()).ok_or(VoxError::Truncated)?;
+
+        let mut models = Vec::new();
+        let mut size: Option<[usize; 3]> = None;
+        let mut palette = default_palette();
+        while reader.cursor < end {
+            let (id, content, children) = reader.chunk_header()?;
+            let mut chunk = VoxReader { bytes: reader.take(content)?, cursor: 0 };
+            reader.skip(children)?;
+
+            match &id {
+                b"SIZE" => size = Some([chunk.u32()? as usize, chunk.u32()? as usize, chunk.u32()? as usize]),
+                b"XYZI" => {
+                    let [width, depth, height] = size.take().ok_or(VoxError::MissingSize)?;
+                    let count = chunk.u32()? as usize;
+                    let mut voxels = Vec::with_capacity(count.min(chunk.bytes.len() / 4));
+                    for _ in 0 .. count {
+                        let voxel = chunk.take(4)?;
+                        let (x, y, z) = (voxel[0] as usize, voxel[1] as usize, voxel[2] as usize);
+                        if x >= width || y >= depth || z >= height { return Err(VoxError::OutOfBounds { model: models.len(), position: [voxel[0], voxel[1], voxel[2]] }) }
+                        voxels.push((Vector3 { x, y: z, z: depth - 1 - y }, voxel[3]));
+                    }
+                    models.push(VoxModel { size: Vector3 { x: width, y: height, z: depth }, voxels });
+                },
+                // the colour of index i is stored at i - 1, with the last entry unused
+                b"RGBA" => for colour in palette[1 ..].iter_mut() { colour.copy_from_slice(chunk.take(4)?); },
+                _ => {}
+            }
+        }
+
+        Ok(Self { models, palette })
+    }
+
+    /// Gets the RGBA colour of the given colour index.
+    pub fn colour(&self, index: u8) -> [u8; 4] { self.palette[index as usize] }
+}
+
+// Reads little endian values from the bytes of a vox file.
+struct VoxReader<'a> {
+    bytes: &'a [u8],
+    cursor: usize
+}
+
+impl <'a> VoxReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], VoxError> {
+        let end = self.cursor.checked_add(count).filter(|end| *end <= self.bytes.len()).ok_or(VoxError::Truncated)?;
+        let bytes = &self.bytes[self.cursor .. end];
+        self.cursor = end;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), VoxError> { self.take(count).map(|_| ()) }
+
+    fn u32(&mut self) -> Result<u32, VoxError> { Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap())) }
+
+    // reads the id, content size and children size of a chunk
+    fn chunk_header(&mut self) -> Result<([u8; 4], usize, usize), VoxError> {
+        let id: [u8; 4] = self.take(4)?.try_into().unwrap();
+        Ok((id, self.u32()? as usize, self.u32()? as usize))
+    }
+}
+
+// The palette MagicaVoxel uses for files without a "RGBA" chunk.  It is a 6x6x6 colour cube without black, followed by ramps of red, green, blue and grey.
+fn default_palette() -> [[u8; 4]; 256] {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut colours = vec![[0, 0, 0, 0]];
+    for r in CUBE { for g in CUBE { for b in CUBE {
+        if r != 0 || g != 0 || b != 0 { colours.push([r, g, b, 0xff]); }
+    } } }
+    colours.extend(RAMP.iter().map(|v| [*v, 0, 0, 0xff]));
+    colours.extend(RAMP.iter().map(|v| [0, *v, 0, 0xff]));
+    colours.extend(RAMP.iter().map(|v| [0, 0, *v, 0xff]));
+    colours.extend(RAMP.iter().map(|v| [*v, *v, *v, 0xff]));
+    colours.try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // builds a chunk with the given id, content and children
+    fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((content.len() as u32).to_le_bytes());
+        bytes.extend((children.len() as u32).to_le_bytes());
+        bytes.extend(content);
+        bytes.extend(children);
+        bytes
+    }
+
+    // builds a vox file with the given chunks inside its main chunk
+    fn vox(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend(150u32.to_le_bytes());
+        bytes.extend(chunk(b"MAIN", &[], &chunks.concat()));
+        bytes
+    }
+
+    fn size(x: u32, y: u32, z: u32) -> Vec<u8> { chunk(b"SIZE", &[x.to_le_bytes(), y.to_le_bytes(), z.to_le_bytes()].concat(), &[]) }
+
+    fn voxels(voxels: &[[u8; 4]]) -> Vec<u8> {
+        let mut content = (voxels.len() as u32).to_le_bytes().to_vec();
+        content.extend(voxels.concat());
+        chunk(b"XYZI", &content, &[])
+    }
+
+    #[test]
+    fn models_are_converted_to_y_up() {
+        // MagicaVoxel is 2 wide, 3 deep and 4 tall, with z up
+        let file = VoxFile::from_bytes(&vox(&[size(2, 3, 4), voxels(&[[1, 0, 3, 7], [0, 2, 0, 9]])])).unwrap();
+        assert_eq!(file.models.len(), 1);
+        assert_eq!(file.models[0].size, Vector3::new(2, 4, 3));
+        assert_eq!(file.models[0].voxels, vec![(Vector3::new(1, 3, 2), 7), (Vector3::new(0, 0, 0), 9)]);
+    }
+
+    #[test]
+    fn palettes_are_shifted_by_one() {
+        let mut colours = vec![[0, 0, 0, 0]; 256];
+        colours[0] = [10, 20, 30, 255];
+        colours[254] = [1, 2, 3, 4];
+        let file = VoxFile::from_bytes(&vox(&[chunk(b"RGBA", &colours.concat(), &[])])).unwrap();
+        assert_eq!(file.colour(1), [10, 20, 30, 255]);
+        assert_eq!(file.colour(255), [1, 2, 3, 4]);
+        assert_eq!(VoxFile::from_bytes(&vox(&[])).unwrap().palette, default_palette());
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        assert!(matches!(VoxFile::from_bytes(b"PNG data"), Err(VoxError::NotVox)));
+        assert!(matches!(VoxFile::from_bytes(&vox(&[voxels(&[[0, 0, 0, 1]])])), Err(VoxError::MissingSize)));
+        assert!(matches!(VoxFile::from_bytes(&vox(&[size(2, 2, 2), voxels(&[[0, 0, 0, 1], [0, 2, 0, 1]])])), Err(VoxError::OutOfBounds { model: 0, position: [0, 2, 0] })));
+
+        // cut off in the middle of the voxels
+        let mut bytes = vox(&[size(2, 2, 2), voxels(&[[0, 0, 0, 1], [1, 1, 1, 1]])]);
+        bytes.truncate(bytes.len() - 2);
+        assert!(matches!(VoxFile::from_bytes(&bytes), Err(VoxError::Truncated)));
+
+        // a voxel count larger than the chunk
+        let mut content = 5u32.to_le_bytes().to_vec();
+        content.extend([0, 0, 0, 1]);
+        assert!(matches!(VoxFile::from_bytes(&vox(&[size(2, 2, 2), chunk(b"XYZI", &content, &[])])), Err(VoxError::Truncated)));
+    }
+}